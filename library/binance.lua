@@ -4,6 +4,7 @@ local util = require("util")
 local decimal = require("decimal")
 local gh = require("gh")
 local json = require("json")
+local orderbook_parser = require("orderbook")
 local send = require("send")
 
 local function extract_data(payload)
@@ -18,6 +19,19 @@ local function extract_data(payload)
 	return obj
 end
 
+-- like `extract_data`, but parsed natively for large payloads
+local function parse_data(payload)
+	local success, data = pcall(json.parse, payload.content)
+	if not success then
+		gh.debug("Failed payload: " .. payload.content)
+		error(tostring(data))
+	end
+	if data:type() == "object" and data:get("code") ~= nil then
+		error({ code = tonumber(data:string("code")), message = data:string("msg") })
+	end
+	return data
+end
+
 ---@class Binance: Exchange
 local M = {}
 
@@ -32,19 +46,16 @@ function M.subscribe_orderbook(market, params)
 		error("unsupported market type " .. market_type)
 	end
 	local symbol = base .. quote
+	local layout = orderbook_parser.layout({ bids = "bids", asks = "asks", price = "1", quantity = "2" })
 
 	local function parse_orderbook(payload)
-		local data = extract_data(payload)
-
-		local orderbook = { bids = {}, asks = {} }
-		for _, v in ipairs(data.bids) do
-			table.insert(orderbook.bids, { price = decimal(v[1]), quantity = decimal(v[2]) })
-		end
-		for _, v in ipairs(data.asks) do
-			table.insert(orderbook.asks, { price = decimal(v[1]), quantity = decimal(v[2]) })
+		local orderbook, err = orderbook_parser.parse(payload, layout)
+		if orderbook == nil then
+			-- raises the API error, if any
+			extract_data(payload)
+			error(err)
 		end
-
-		return common.wrap_orderbook(orderbook)
+		return orderbook
 	end
 
 	local req = M.build_request(
@@ -71,22 +82,22 @@ function M.subscribe_balance(market_type, params)
 	end
 
 	local function parse_balance(payload)
-		local data = extract_data(payload)
+		local data = parse_data(payload)
 		local balance = {}
 		if market_type == "spot" then
-			for _, v in ipairs(data.balances) do
-				if not (util.is_zero(v.free) and util.is_zero(v.locked) and util.is_zero(v.total)) then
-					local free = decimal(v.free)
-					local locked = decimal(v.locked)
-					balance[v.asset] = { free = free, locked = locked, total = free + locked }
+			for _, v in data:get("balances"):ipairs() do
+				local free = v:decimal("free")
+				local locked = v:decimal("locked")
+				if not (free:is_zero() and locked:is_zero()) then
+					balance[v:string("asset")] = { free = free, locked = locked, total = free + locked }
 				end
 			end
 		else
-			for _, v in ipairs(data) do
-				if not (util.is_zero(v.maxWithdrawAmount) and util.is_zero(v.availableBalance)) then
-					local free = decimal(v.maxWithdrawAmount)
-					local total = decimal(v.availableBalance)
-					balance[v.asset] = { free = free, locked = total - free, total = total }
+			for _, v in data:ipairs() do
+				local free = v:decimal("maxWithdrawAmount")
+				local total = v:decimal("availableBalance")
+				if not (free:is_zero() and total:is_zero()) then
+					balance[v:string("asset")] = { free = free, locked = total - free, total = total }
 				end
 			end
 		end
@@ -110,21 +121,15 @@ function M.subscribe_orders(market, params)
 	end
 
 	local function parse_orders(payload)
-		local data = extract_data(payload)
+		local data = parse_data(payload)
 		local orders = {}
-		for _, v in ipairs(data) do
-			if v.status == "NEW" then
-				if v.side == "BUY" then
-					table.insert(
-						orders,
-						{ id = tostring(v.orderId), price = decimal(v.price), amount = decimal(v.origQty) }
-					)
-				else
-					table.insert(
-						orders,
-						{ id = tostring(v.orderId), price = decimal(v.price), amount = -decimal(v.origQty) }
-					)
+		for _, v in data:ipairs() do
+			if v:string("status") == "NEW" then
+				local amount = v:decimal("origQty")
+				if v:string("side") ~= "BUY" then
+					amount = -amount
 				end
+				table.insert(orders, { id = v:string("orderId"), price = v:decimal("price"), amount = amount })
 			end
 		end
 		return common.wrap_orders(orders)
@@ -12,7 +12,16 @@ function M.cdef()
             uint8_t raw[16];
         } decimal_t;
 
+        typedef struct {
+            uint8_t status;
+            decimal_t value;
+        } decimal_result_t;
+
         decimal_t decimal_from_string(const uint8_t*, uint32_t len);
+        decimal_result_t decimal_from_f64(double);
+        decimal_t decimal_from_i64(int64_t);
+        decimal_t decimal_from_u64(uint64_t);
+        decimal_result_t decimal_from_parts(int64_t, uint32_t);
         uint8_t* decimal_to_string(decimal_t);
         decimal_t decimal_add(decimal_t, decimal_t);
         decimal_t decimal_sub(decimal_t, decimal_t);
@@ -31,23 +40,119 @@ function M.cdef()
         decimal_t decimal_max(decimal_t, decimal_t);
         decimal_t decimal_min(decimal_t, decimal_t);
 
+        decimal_result_t decimal_checked_from_string(LuaStr);
+        decimal_result_t decimal_checked_add(decimal_t, decimal_t);
+        decimal_result_t decimal_checked_sub(decimal_t, decimal_t);
+        decimal_result_t decimal_checked_mul(decimal_t, decimal_t);
+        decimal_result_t decimal_checked_div(decimal_t, decimal_t);
+        decimal_result_t decimal_checked_mod(decimal_t, decimal_t);
+        decimal_result_t decimal_checked_pow(decimal_t, decimal_t);
+
+        decimal_result_t decimal_sqrt(decimal_t);
+        decimal_result_t decimal_ln(decimal_t);
+        decimal_result_t decimal_exp(decimal_t);
+        decimal_t decimal_trunc(decimal_t);
+        decimal_t decimal_signum(decimal_t);
+        bool decimal_is_zero(decimal_t);
+        uint32_t decimal_scale(decimal_t);
+        decimal_t decimal_rescale(decimal_t, uint32_t);
+        decimal_t decimal_normalize(decimal_t);
+        double decimal_to_number(decimal_t);
+        decimal_result_t decimal_floor_to_step(decimal_t, decimal_t);
+        decimal_result_t decimal_ceil_to_step(decimal_t, decimal_t);
+        decimal_result_t decimal_round_to_step(decimal_t, decimal_t, uint32_t);
+
         decimal_t millis(void);
+        decimal_t epoch_millis(void);
+        decimal_t epoch_nanos(void);
+        uint8_t advance_clock(decimal_t);
         void report_timings(LuaStr, decimal_t, decimal_t);
+        uint8_t set_gauge(LuaStr, LuaStr, decimal_t);
+        uint8_t add_gauge(LuaStr, LuaStr, decimal_t);
+        uint8_t inc_counter(LuaStr, LuaStr, decimal_t);
+        uint8_t observe_histogram(LuaStr, LuaStr, decimal_t);
+
+        decimal_t tick_price_unit(LuaStr, decimal_t, bool);
+        decimal_t tick_next_price_up(LuaStr, decimal_t);
+        decimal_t tick_next_price_down(LuaStr, decimal_t);
+        bool tick_is_valid_price(LuaStr, decimal_t);
 
         void free_string(char*);
     ]])
 end
 
+-- messages of `DecimalStatus` values
+local status_messages = {
+	[1] = "overflow",
+	[2] = "division by zero",
+	[3] = "invalid operand",
+}
+
+-- `result.value` refers into `result` without keeping it alive, so results are copied out
+---@param f function checked operation
+---@param op string
+local function checked_binop(f, op)
+	return function(x, y)
+		local result = f(x, y)
+		if result.status ~= 0 then
+			error(string.format("%s: %s %s %s", status_messages[result.status], tostring(x), op, tostring(y)), 2)
+		end
+		return ffi.new("decimal_t", result.value)
+	end
+end
+
+---@param f function checked operation
+---@param name string
+local function checked_unop(f, name)
+	return function(x)
+		local result = f(x)
+		if result.status ~= 0 then
+			error(string.format("%s: %s(%s)", status_messages[result.status], name, tostring(x)), 2)
+		end
+		return ffi.new("decimal_t", result.value)
+	end
+end
+
+---@param f function checked operation
+---@param name string
+local function checked_method(f, name)
+	return function(x, ...)
+		local result = f(x, ...)
+		if result.status ~= 0 then
+			local args = {}
+			for i = 1, select("#", ...) do
+				args[i] = tostring(select(i, ...))
+			end
+			error(
+				string.format("%s: %s:%s(%s)", status_messages[result.status], tostring(x), name, table.concat(args, ", ")),
+				2
+			)
+		end
+		return ffi.new("decimal_t", result.value)
+	end
+end
+
+-- values of `rounding_strategy` in `lua_decimal.rs`
+local rounding_modes = {
+	half_even = 0,
+	half_away_from_zero = 1,
+	half_toward_zero = 2,
+	toward_zero = 3,
+	away_from_zero = 4,
+	floor = 5,
+	ceil = 6,
+}
+
 function M.set_clib(clib)
 	gh = clib
 
 	ffi.metatype("decimal_t", {
-		__add = gh.decimal_add,
-		__sub = gh.decimal_sub,
-		__mul = gh.decimal_mul,
-		__div = gh.decimal_div,
-		__mod = gh.decimal_mod,
-		__pow = gh.decimal_pow,
+		__add = checked_binop(gh.decimal_checked_add, "+"),
+		__sub = checked_binop(gh.decimal_checked_sub, "-"),
+		__mul = checked_binop(gh.decimal_checked_mul, "*"),
+		__div = checked_binop(gh.decimal_checked_div, "/"),
+		__mod = checked_binop(gh.decimal_checked_mod, "%"),
+		__pow = checked_binop(gh.decimal_checked_pow, "^"),
 		__unm = function(x)
 			return gh.decimal_unm(x)
 		end,
@@ -73,6 +178,38 @@ function M.set_clib(clib)
 			round_to_decimals = gh.decimal_round_to_decimals,
 			max = gh.decimal_max,
 			min = gh.decimal_min,
+			sqrt = checked_unop(gh.decimal_sqrt, "sqrt"),
+			ln = checked_unop(gh.decimal_ln, "ln"),
+			exp = checked_unop(gh.decimal_exp, "exp"),
+			trunc = gh.decimal_trunc,
+			signum = gh.decimal_signum,
+			is_zero = gh.decimal_is_zero,
+			scale = gh.decimal_scale,
+			rescale = gh.decimal_rescale,
+			normalize = gh.decimal_normalize,
+			to_number = gh.decimal_to_number,
+			floor_to_step = checked_method(gh.decimal_floor_to_step, "floor_to_step"),
+			ceil_to_step = checked_method(gh.decimal_ceil_to_step, "ceil_to_step"),
+			round_to_step = function(x, step, mode)
+				local mode_value = rounding_modes[mode or "half_even"]
+				if mode_value == nil then
+					error(string.format("unknown rounding mode %s", mode), 2)
+				end
+				local result = gh.decimal_round_to_step(x, step, mode_value)
+				if result.status ~= 0 then
+					error(
+						string.format(
+							"%s: %s:round_to_step(%s, %s)",
+							status_messages[result.status],
+							tostring(x),
+							tostring(step),
+							mode or "half_even"
+						),
+						2
+					)
+				end
+				return ffi.new("decimal_t", result.value)
+			end,
 		},
 		__newindex = function()
 			error("field assignment not allowed in decimals", 2)
@@ -83,23 +220,47 @@ function M.set_clib(clib)
 	})
 end
 
+---Returns `mantissa * 10^-scale`, e.g. `from_parts(12345, 2)` is `123.45`.
+---@param mantissa integer | ffi.cdata* Lua number or `int64_t`
+---@param scale integer at most 28
+---@return Decimal
+function M.from_parts(mantissa, scale)
+	local result = gh.decimal_from_parts(mantissa, scale)
+	if result.status ~= 0 then
+		error(string.format("invalid decimal parts: %s, %s", tostring(mantissa), tostring(scale)), 2)
+	end
+	return ffi.new("decimal_t", result.value)
+end
+
 ---@diagnostic disable-next-line
 setmetatable(M, {
 	__call = function(_, x)
 		if x == 0 then
 			return ffi.new("decimal_t")
 		end
-		local value
 		if type(x) == "number" then
-			x = tostring(x)
-			value = gh.decimal_from_string(x, #x)
-		elseif type(x) == "string" then
-			value = gh.decimal_from_string(x, #x)
-		else
+			local result = gh.decimal_from_f64(x)
+			if result.status ~= 0 then
+				error(string.format("invalid decimal: %s", tostring(x)), 2)
+			end
+			return ffi.new("decimal_t", result.value)
+		elseif type(x) == "cdata" then
+			-- 64-bit integers are converted exactly, unlike Lua numbers
+			if ffi.istype("int64_t", x) then
+				return gh.decimal_from_i64(x)
+			elseif ffi.istype("uint64_t", x) then
+				return gh.decimal_from_u64(x)
+			end
+			error("unsupported cdata " .. tostring(ffi.typeof(x)) .. " for decimal constructor", 2)
+		elseif type(x) ~= "string" then
 			error("unsupported type " .. type(x) .. " for decimal constructor", 1)
 		end
+		local result = gh.decimal_checked_from_string({ ptr = x, len = #x })
+		if result.status ~= 0 then
+			error(string.format("invalid decimal: %q", x), 2)
+		end
 
-		return value
+		return ffi.new("decimal_t", result.value)
 	end,
 })
 
@@ -42,10 +42,35 @@ local function resume_strategy(strategy_name, ...)
 		context.reset_strategy_local(strategy_name)
 		gh.error("strategy " .. strategy_name .. " ended with an error: " .. tostring(ret))
 		strategies[strategy_name].error = ret
-	elseif coroutine.status(strategies[strategy_name].coro) == "dead" then
-		in_strategy_ctx(strategy_name, util.execute_atexit)
-		context.reset_strategy_local(strategy_name)
-		gh.info("strategy " .. strategy_name .. " ended without an error")
+		gh.report_strategy_status(strategy_name, "error")
+	else
+		if coroutine.status(strategies[strategy_name].coro) == "dead" then
+			in_strategy_ctx(strategy_name, util.execute_atexit)
+			context.reset_strategy_local(strategy_name)
+			gh.info("strategy " .. strategy_name .. " ended without an error")
+		end
+		gh.report_strategy_status(strategy_name, coroutine.status(strategies[strategy_name].coro))
+	end
+	return ret
+end
+
+---Returns the set of loaded strategies which subscribed to the source of the event.
+---Subscriptions made outside of any strategy are delivered to every strategy.
+---@param ev Event
+---@return { [string]: Strategy }
+local function event_owners(ev)
+	local owners = ev.owners
+	if owners == nil then
+		return strategies
+	end
+	local ret = {}
+	for _, strategy_name in ipairs(owners) do
+		if strategy_name == "" then
+			return strategies
+		end
+		if strategies[strategy_name] ~= nil then
+			ret[strategy_name] = strategies[strategy_name]
+		end
 	end
 	return ret
 end
@@ -67,6 +92,24 @@ local function load_strategy(strategy_name)
 	end
 end
 
+---Stops a running strategy and loads it again, leaving the others running.
+---@param strategy_name string
+local function restart_strategy(strategy_name)
+	local strategy = strategies[strategy_name]
+	if strategy == nil then
+		gh.warn("cannot restart unknown strategy " .. strategy_name)
+		return
+	end
+	gh.info("restarting strategy " .. strategy_name)
+	if coroutine.status(strategy.coro) ~= "dead" then
+		in_strategy_ctx(strategy_name, util.execute_atexit)
+	end
+	strategies[strategy_name] = nil
+	context.reset_strategy_local(strategy_name)
+	gh.unsubscribe_strategy(strategy_name)
+	load_strategy(strategy_name)
+end
+
 function M.event_loop()
 	strategies = {} -- for the cast of 2nd or more run due to restarts
 
@@ -88,20 +131,33 @@ function M.event_loop()
 				if ev.response_payload.terminate then
 					error_kind = M.interrupts.terminate
 				elseif ev.response_payload.restart then
+					-- restarts only the given strategies, if any
+					local owners = ev.owners
+					if owners ~= nil then
+						for _, strategy_name in ipairs(owners) do
+							restart_strategy(strategy_name)
+						end
+						return
+					end
 					error_kind = M.interrupts.restart
 				else
 					error("unknown signal payload")
 				end
 				error(error_kind)
-			elseif ev.kind == "fetcher" then
+			elseif ev.kind == "fetcher" or ev.kind == "ws" then
 				if ev.response_payload.error then
 					gh.error(ev.response_payload.content)
 				else
-					for strategy_name in pairs(strategies) do
+					for strategy_name in pairs(event_owners(ev)) do
 						in_strategy_ctx(strategy_name, router.deliver_fetcher_payload, ev.response_payload)
 					end
 				end
-			elseif ev.kind == "send_response" then
+			elseif
+				ev.kind == "send_response"
+				or ev.kind == "rate_limited"
+				or ev.kind == "trading_halted"
+				or ev.kind == "timer"
+			then
 				-- nothing to do
 			else
 				gh.warn(string.format("unknown event kind %s", ev.kind))
@@ -147,10 +203,13 @@ function M.event_loop()
 			if coroutine.status(strategy.coro) == "dead" then
 				strategies[strategy_name] = nil
 				context.reset_strategy_local(strategy_name)
+				gh.unsubscribe_strategy(strategy_name)
 				if strategy.error ~= nil then
 					gh.error(tostring(strategy.error))
 					gh.debug("reloading strategy " .. strategy_name)
 					load_strategy(strategy_name)
+				else
+					gh.report_strategy_status(strategy_name, "")
 				end
 			end
 		end
@@ -172,7 +231,7 @@ function M.clear_strategies()
 			break
 		end
 		local should_break = true
-		if ev.kind == "send_response" then
+		if ev.kind == "send_response" or ev.kind == "rate_limited" or ev.kind == "trading_halted" then
 			for strategy_name, atexit_coro in pairs(atexit_coros) do
 				if coroutine.status(atexit_coro) == "suspended" then
 					local want = context._want(strategy_name)
@@ -4,6 +4,7 @@ local util = require("util")
 local decimal = require("decimal")
 local gh = require("gh")
 local json = require("json")
+local orderbook_parser = require("orderbook")
 local send = require("send")
 
 local function extract_data(payload)
@@ -51,27 +52,21 @@ function M.subscribe_orderbook(market, params)
 		error("unsupported market type " .. market_type)
 	end
 
-	local function parse_orderbook(payload)
-		local data = extract_data(payload)
+	local layout
+	if market_type == "spot" then
+		layout = orderbook_parser.layout({ bids = "bids", asks = "asks", price = "1", quantity = "2" })
+	else
+		layout = orderbook_parser.layout({ bids = "bids", asks = "asks", price = "p", quantity = "s" })
+	end
 
-		local orderbook = { bids = {}, asks = {} }
-		if market_type == "spot" then
-			for _, v in ipairs(data.bids) do
-				table.insert(orderbook.bids, { price = decimal(v[1]), quantity = decimal(v[2]) })
-			end
-			for _, v in ipairs(data.asks) do
-				table.insert(orderbook.asks, { price = decimal(v[1]), quantity = decimal(v[2]) })
-			end
-		else
-			for _, v in ipairs(data.bids) do
-				table.insert(orderbook.bids, { price = decimal(v.p), quantity = decimal(v.s) })
-			end
-			for _, v in ipairs(data.asks) do
-				table.insert(orderbook.asks, { price = decimal(v.p), quantity = decimal(v.s) })
-			end
+	local function parse_orderbook(payload)
+		local orderbook, err = orderbook_parser.parse(payload, layout)
+		if orderbook == nil then
+			-- raises the API error, if any
+			extract_data(payload)
+			error(err)
 		end
-
-		return common.wrap_orderbook(orderbook)
+		return orderbook
 	end
 
 	local req = M.build_request(endpoint, "get", util.apply_default(params, default_params))
@@ -1,5 +1,7 @@
 local ffi = require("ffi")
 local json = require("json")
+local context = require("context")
+local decimal = require("decimal")
 
 ---@type any
 local gh = {}
@@ -10,6 +12,7 @@ local M = {}
 ---@field url string
 ---@field content string | nil
 ---@field env_suffix string | nil
+---@field headers {[string]: string} response headers keyed by lowercase names
 ---@field status number
 ---@field error boolean
 ---@field restart boolean
@@ -18,6 +21,7 @@ local M = {}
 ---@field kind string
 ---@field response_payload ResponsePayload
 ---@field token ffi.cdata*
+---@field owners string[] | nil names of the strategies subscribed to the event source
 
 function M.cdef()
 	ffi.cdef([[
@@ -31,6 +35,9 @@ function M.cdef()
             const uint8_t *env_suffix_ptr;
             size_t env_suffix_len;
             size_t env_suffix_cap;
+            const uint8_t *headers_ptr;
+            size_t headers_len;
+            size_t headers_cap;
             uint16_t status;
             bool error;
             bool restart;
@@ -43,6 +50,9 @@ function M.cdef()
             size_t kind_cap;
             const ResponsePayload *_response_payload;
             uint64_t token;
+            const uint8_t *owners_ptr;
+            size_t owners_len;
+            size_t owners_cap;
         } Event;
 
         typedef struct {
@@ -51,9 +61,15 @@ function M.cdef()
         } LuaStr;
 
         void subscribe_rest_events(LuaStr, double);
+        void unsubscribe_rest_events(LuaStr);
+        void unsubscribe_strategy(LuaStr);
+        void subscribe_ws_events(LuaStr);
         Event next_event(void);
         void free_event(Event);
         uint64_t send_payload(LuaStr);
+        uint64_t schedule_timer(double, bool);
+        void cancel_timer(uint64_t);
+        uint8_t configure_rate_limit(LuaStr);
         uint8_t* list_strategies(void);
         const ResponsePayload* get_response_payload(Event);
         void free_response_payload(const ResponsePayload*);
@@ -61,6 +77,10 @@ function M.cdef()
         void initialize(void);
         void deinitialize(void);
         void reset_metrics(LuaStr);
+        void report_strategy_status(LuaStr, LuaStr);
+        uint8_t register_gauge(LuaStr, LuaStr, LuaStr);
+        uint8_t register_counter(LuaStr, LuaStr, LuaStr);
+        uint8_t register_histogram(LuaStr, LuaStr, LuaStr, LuaStr);
 
         void set_script_name(LuaStr);
         void trace(LuaStr);
@@ -68,6 +88,7 @@ function M.cdef()
         void info(LuaStr);
         void warn(LuaStr);
         void error(LuaStr);
+        void log(LuaStr, LuaStr, LuaStr);
         void notice(LuaStr);
         void emergency(LuaStr);
     ]])
@@ -80,6 +101,25 @@ function M._subscribe(payload, period_ms)
 	gh.subscribe_rest_events({ ptr = s, len = #s }, period_ms)
 end
 
+---Releases a subscription made by `_subscribe` with the same payload.
+---@param payload table
+function M._unsubscribe(payload)
+	local s = json.encode(payload)
+	gh.unsubscribe_rest_events({ ptr = s, len = #s })
+end
+
+---Releases every subscription held by the strategy.
+---@param strategy_name string
+function M.unsubscribe_strategy(strategy_name)
+	gh.unsubscribe_strategy({ ptr = strategy_name, len = #strategy_name })
+end
+
+---@param payload WsRequest
+function M._subscribe_ws(payload)
+	local s = json.encode(payload)
+	gh.subscribe_ws_events({ ptr = s, len = #s })
+end
+
 ---@return Event
 function M.next_event()
 	---@diagnostic disable-next-line
@@ -97,6 +137,47 @@ function M._send(payload)
 	return gh.send_payload({ ptr = s, len = #s })
 end
 
+---Schedules a `timer` event with the returned token after `delay_ms` milliseconds, and every
+---`delay_ms` milliseconds after that if `repeat` is set.
+---@param delay_ms number
+---@param repeat_ boolean | nil
+---@return ffi.cdata*
+function M.schedule_timer(delay_ms, repeat_)
+	return gh.schedule_timer(delay_ms, repeat_ == true)
+end
+
+---@param token ffi.cdata*
+function M.cancel_timer(token)
+	gh.cancel_timer(token)
+end
+
+---Suspends the current strategy for `ms` milliseconds. Only usable in the strategy context.
+---@param ms number
+function M.sleep(ms)
+	-- required lazily since timer depends on this module
+	local timer = require("timer")
+	local token = M.schedule_timer(ms, false)
+	timer.pause()
+	context.yield(function(ev)
+		if ev.kind == "timer" and ev.token == token then
+			return true
+		end
+	end)
+	timer.resume()
+end
+
+---Configures the rate limit shared by the subscriptions and sends to `config.key`.
+---@param config RateLimitConfig
+function M.configure_rate_limit(config)
+	local s = json.encode(config)
+	local status = gh.configure_rate_limit({ ptr = s, len = #s })
+	if status == 1 then
+		error("invalid rate limit config " .. s, 2)
+	elseif status == 2 then
+		error("rate limit and interval must be positive: " .. s, 2)
+	end
+end
+
 function M.list_strategies()
 	local s = gh.list_strategies()
 	local ss = ffi.string(s)
@@ -116,33 +197,190 @@ function M.reset_metrics(strategy_name)
 	gh.reset_metrics({ ptr = strategy_name, len = #strategy_name })
 end
 
+---Reports the coroutine status of a strategy to the admin API. An empty status removes it.
+---@param strategy_name string
+---@param status string
+function M.report_strategy_status(strategy_name, status)
+	gh.report_strategy_status({ ptr = strategy_name, len = #strategy_name }, { ptr = status, len = #status })
+end
+
+-- messages of `MetricStatus` values
+local metric_status_messages = {
+	[1] = "label names must be an array of strings",
+	[2] = "label name strategy is reserved",
+	[3] = "histogram buckets must be an array of numbers",
+	[4] = "invalid metric or label name",
+	[5] = "metric already registered with another kind or labels",
+	[6] = "metric not registered",
+	[7] = "metric of another kind",
+	[8] = "labels must be a table keyed by label names",
+	[9] = "missing metric label",
+	[10] = "unknown metric label",
+	[11] = "counters cannot decrease",
+}
+
+---@param status integer `MetricStatus` returned by a metric function
+---@param name string
+---@param labels string JSON label names or values
+local function check_metric_status(status, name, labels)
+	if status ~= 0 then
+		error(string.format("%s: metric %s, labels %s", metric_status_messages[status], name, labels), 3)
+	end
+end
+
+---Registers a gauge for `gh.set_gauge()` and `gh.add_gauge()`, exported on `/metrics`. Series are
+---labelled by the strategy name and `label_names`, and removed when the strategy is reloaded.
+---Registering again with the same labels does nothing.
+---@param name string
+---@param help string
+---@param label_names string[] | nil
+function M.register_gauge(name, help, label_names)
+	local s = json.encode(label_names or {})
+	local status = gh.register_gauge({ ptr = name, len = #name }, { ptr = help, len = #help }, { ptr = s, len = #s })
+	check_metric_status(status, name, s)
+end
+
+---Registers a counter for `gh.inc_counter()`, like `gh.register_gauge()`.
+---@param name string
+---@param help string
+---@param label_names string[] | nil
+function M.register_counter(name, help, label_names)
+	local s = json.encode(label_names or {})
+	local status = gh.register_counter({ ptr = name, len = #name }, { ptr = help, len = #help }, { ptr = s, len = #s })
+	check_metric_status(status, name, s)
+end
+
+---Registers a histogram for `gh.observe_histogram()`, like `gh.register_gauge()`.
+---@param name string
+---@param help string
+---@param label_names string[] | nil
+---@param buckets number[] | nil upper bounds of the buckets, Prometheus defaults if nil
+function M.register_histogram(name, help, label_names, buckets)
+	local s = json.encode(label_names or {})
+	local b = buckets and json.encode(buckets) or ""
+	local status = gh.register_histogram(
+		{ ptr = name, len = #name },
+		{ ptr = help, len = #help },
+		{ ptr = s, len = #s },
+		{ ptr = b, len = #b }
+	)
+	check_metric_status(status, name, s)
+end
+
+---@param name string
+---@param value Decimal
+---@param labels {[string]: string} | nil values of the labels other than the strategy name
+function M.set_gauge(name, value, labels)
+	local s = json.encode(labels or {})
+	local status = gh.set_gauge({ ptr = name, len = #name }, { ptr = s, len = #s }, value)
+	check_metric_status(status, name, s)
+end
+
+---@param name string
+---@param value Decimal
+---@param labels {[string]: string} | nil values of the labels other than the strategy name
+function M.add_gauge(name, value, labels)
+	local s = json.encode(labels or {})
+	local status = gh.add_gauge({ ptr = name, len = #name }, { ptr = s, len = #s }, value)
+	check_metric_status(status, name, s)
+end
+
+---@param name string
+---@param value Decimal | nil non-negative increment, 1 if nil
+---@param labels {[string]: string} | nil values of the labels other than the strategy name
+function M.inc_counter(name, value, labels)
+	local s = json.encode(labels or {})
+	local status = gh.inc_counter({ ptr = name, len = #name }, { ptr = s, len = #s }, value or decimal(1))
+	check_metric_status(status, name, s)
+end
+
+---@param name string
+---@param value Decimal
+---@param labels {[string]: string} | nil values of the labels other than the strategy name
+function M.observe_histogram(name, value, labels)
+	local s = json.encode(labels or {})
+	local status = gh.observe_histogram({ ptr = name, len = #name }, { ptr = s, len = #s }, value)
+	check_metric_status(status, name, s)
+end
+
 ---@param name string
 function M.set_script_name(name)
 	gh.set_script_name({ ptr = name, len = #name })
 end
 
+local log_levels = { trace = true, debug = true, info = true, warn = true, error = true }
+
+---Converts decimals in `value` to strings, recursing into tables.
+local function log_value(value)
+	if type(value) == "cdata" then
+		return tostring(value)
+	elseif type(value) == "table" then
+		local converted = {}
+		for key, x in pairs(value) do
+			converted[key] = log_value(x)
+		end
+		return converted
+	end
+	return value
+end
+
+---Logs `message` with `fields` as structured fields of the event, e.g.
+---`gh.log("info", "order placed", { price = price, side = "buy" })`. Decimals are recorded as
+---strings, and tables as JSON. Field names should not be dynamic: beyond 1024 distinct sets of
+---names, fields are recorded as JSON in a single `fields` field.
+---@param level "trace" | "debug" | "info" | "warn" | "error"
 ---@param message string
-function M.trace(message)
+---@param fields table<string, any> | nil
+function M.log(level, message, fields)
+	if not log_levels[level] then
+		error("invalid log level " .. tostring(level), 2)
+	end
+	local s = json.encode(log_value(fields or {}))
+	gh.log({ ptr = level, len = #level }, { ptr = message, len = #message }, { ptr = s, len = #s })
+end
+
+---@param message string
+---@param fields table<string, any> | nil structured fields, see `gh.log()`
+function M.trace(message, fields)
+	if fields ~= nil then
+		return M.log("trace", message, fields)
+	end
 	gh.trace({ ptr = message, len = #message })
 end
 
 ---@param message string
-function M.debug(message)
+---@param fields table<string, any> | nil structured fields, see `gh.log()`
+function M.debug(message, fields)
+	if fields ~= nil then
+		return M.log("debug", message, fields)
+	end
 	gh.debug({ ptr = message, len = #message })
 end
 
 ---@param message string
-function M.info(message)
+---@param fields table<string, any> | nil structured fields, see `gh.log()`
+function M.info(message, fields)
+	if fields ~= nil then
+		return M.log("info", message, fields)
+	end
 	gh.info({ ptr = message, len = #message })
 end
 
 ---@param message string
-function M.warn(message)
+---@param fields table<string, any> | nil structured fields, see `gh.log()`
+function M.warn(message, fields)
+	if fields ~= nil then
+		return M.log("warn", message, fields)
+	end
 	gh.warn({ ptr = message, len = #message })
 end
 
 ---@param message string
-function M.error(message)
+---@param fields table<string, any> | nil structured fields, see `gh.log()`
+function M.error(message, fields)
+	if fields ~= nil then
+		return M.log("error", message, fields)
+	end
 	gh.error({ ptr = message, len = #message })
 end
 
@@ -156,11 +394,69 @@ function M.emergency(message)
 	gh.emergency({ ptr = message, len = #message })
 end
 
+---Milliseconds elapsed on the monotonic clock.
 ---@return Decimal
 function M.millis()
 	return gh.millis()
 end
 
+---Milliseconds since the Unix epoch.
+---@return Decimal
+function M.epoch_millis()
+	return gh.epoch_millis()
+end
+
+---Nanoseconds since the Unix epoch.
+---@return Decimal
+function M.epoch_nanos()
+	return gh.epoch_nanos()
+end
+
+---Advances the virtual clock to `epoch_millis` milliseconds since the Unix epoch. Only usable if
+---`GRASSHOPPER_VIRTUAL_CLOCK` is set.
+---@param epoch_millis Decimal
+function M.advance_clock(epoch_millis)
+	local status = gh.advance_clock(epoch_millis)
+	if status == 1 then
+		error("advance_clock requires the virtual clock", 2)
+	elseif status == 2 then
+		error("clock time out of range: " .. tostring(epoch_millis), 2)
+	end
+end
+
+---Price unit of `exchange` at `price`. At tier boundaries, returns the unit of the upper tier, or
+---the one of the lower tier if `below` is set.
+---@param exchange string
+---@param price Decimal
+---@param below boolean | nil
+---@return Decimal
+function M.price_unit(exchange, price, below)
+	return gh.tick_price_unit({ ptr = exchange, len = #exchange }, price, below == true)
+end
+
+---Smallest valid price of `exchange` greater than `price`.
+---@param exchange string
+---@param price Decimal
+---@return Decimal
+function M.next_price_up(exchange, price)
+	return gh.tick_next_price_up({ ptr = exchange, len = #exchange }, price)
+end
+
+---Largest valid price of `exchange` less than `price`.
+---@param exchange string
+---@param price Decimal
+---@return Decimal
+function M.next_price_down(exchange, price)
+	return gh.tick_next_price_down({ ptr = exchange, len = #exchange }, price)
+end
+
+---@param exchange string
+---@param price Decimal
+---@return boolean
+function M.is_valid_price(exchange, price)
+	return gh.tick_is_valid_price({ ptr = exchange, len = #exchange }, price)
+end
+
 ---@param strategy_name string
 ---@param elapsed Decimal
 ---@param wall_elapsed Decimal
@@ -176,6 +472,12 @@ function M.set_clib(x)
 				return ffi.string(self.kind_ptr, self.kind_len)
 			elseif key == "response_payload" then
 				return ffi.gc(gh.get_response_payload(self), gh.free_response_payload)
+			elseif key == "owners" then
+				if self.owners_ptr ~= nil then
+					return json.decode(ffi.string(self.owners_ptr, self.owners_len))
+				else
+					return nil
+				end
 			end
 		end,
 	})
@@ -199,6 +501,12 @@ function M.set_clib(x)
 				else
 					return nil
 				end
+			elseif key == "headers" then
+				if self.headers_ptr ~= nil then
+					return json.decode(ffi.string(self.headers_ptr, self.headers_len))
+				else
+					return {}
+				end
 			end
 		end,
 	})
@@ -1,8 +1,32 @@
 local ffi = require("ffi")
 local json_external = require("json_external")
 
+---@type any
+local gh = {}
+
 local M = {}
 
+function M.cdef()
+	ffi.cdef([[
+        typedef struct JsonDocument JsonDocument;
+
+        JsonDocument* json_parse(LuaStr);
+        void json_free(JsonDocument*);
+        const char* json_error(const JsonDocument*);
+        int64_t json_find(const JsonDocument*, uint32_t, LuaStr);
+        uint8_t json_type(const JsonDocument*, uint32_t);
+        uint32_t json_len(const JsonDocument*, uint32_t);
+        int64_t json_child(const JsonDocument*, uint32_t, uint32_t);
+        LuaStr json_key(const JsonDocument*, uint32_t, uint32_t);
+        LuaStr json_text(const JsonDocument*, uint32_t);
+        decimal_result_t json_decimal(const JsonDocument*, uint32_t);
+    ]])
+end
+
+function M.set_clib(clib)
+	gh = clib
+end
+
 ---@param x string
 ---@return any
 function M.decode(x)
@@ -15,4 +39,164 @@ function M.encode(x)
 	return json_external.encode(x)
 end
 
+-- values of `json_type` in `json.rs`
+local node_types = { [0] = "null", "boolean", "number", "string", "array", "object" }
+
+---Node of a document parsed by `json.parse`. Methods taking `path` query the node at the dotted
+---path from this one instead, e.g. `data:decimal("balances.1.free")`; array positions count from
+---1. They return nil if there is no such node or it is null.
+---@class JsonNode
+---@field doc ffi.cdata*
+---@field index integer
+local Node = {}
+Node.__index = Node
+
+local function new_node(doc, index)
+	return setmetatable({ doc = doc, index = index }, Node)
+end
+
+---@param path string | nil
+---@return JsonNode | nil
+function Node:get(path)
+	if path == nil then
+		return self
+	end
+	local index = gh.json_find(self.doc, self.index, { ptr = path, len = #path })
+	if index < 0 then
+		return nil
+	end
+	return new_node(self.doc, tonumber(index))
+end
+
+---@param path string | nil
+---@return "null" | "boolean" | "number" | "string" | "array" | "object" | nil
+function Node:type(path)
+	local node = self:get(path)
+	if node == nil then
+		return nil
+	end
+	return node_types[gh.json_type(node.doc, node.index)]
+end
+
+---Number of items of an array or members of an object.
+---@return integer
+function Node:len()
+	return gh.json_len(self.doc, self.index)
+end
+
+---Returns the content of a string, or the source text of a number, so large integers such as
+---order IDs are kept exactly.
+---@param path string | nil
+---@return string | nil
+function Node:string(path)
+	local node = self:get(path)
+	local node_type = node and node:type()
+	if node_type == nil or node_type == "null" then
+		return nil
+	elseif node_type == "array" or node_type == "object" then
+		error(string.format("JSON %s is not a scalar", node_type), 2)
+	end
+	local s = gh.json_text(node.doc, node.index)
+	return ffi.string(s.ptr, s.len)
+end
+
+---Converts a number, or a string holding one, without loss.
+---@param path string | nil
+---@return Decimal | nil
+function Node:decimal(path)
+	local node = self:get(path)
+	if node == nil or node:type() == "null" then
+		return nil
+	end
+	local result = gh.json_decimal(node.doc, node.index)
+	if result.status ~= 0 then
+		local s = gh.json_text(node.doc, node.index)
+		error(string.format("invalid decimal in JSON: %q", ffi.string(s.ptr, s.len)), 2)
+	end
+	return ffi.new("decimal_t", result.value)
+end
+
+---@param path string | nil
+---@return boolean | nil
+function Node:boolean(path)
+	local node = self:get(path)
+	local node_type = node and node:type()
+	if node_type == nil or node_type == "null" then
+		return nil
+	elseif node_type ~= "boolean" then
+		error(string.format("JSON %s is not a boolean", node_type), 2)
+	end
+	return node:string() == "true"
+end
+
+---Iterates over the items of an array.
+---@return fun(): integer, JsonNode
+function Node:ipairs()
+	local len = self:len()
+	local i = 0
+	return function()
+		if i < len then
+			i = i + 1
+			return i, new_node(self.doc, tonumber(gh.json_child(self.doc, self.index, i - 1)))
+		end
+	end
+end
+
+---Iterates over the members of an object, in document order.
+---@return fun(): string, JsonNode
+function Node:pairs()
+	local len = self:len()
+	local i = 0
+	return function()
+		if i < len then
+			i = i + 1
+			local key = gh.json_key(self.doc, self.index, i - 1)
+			if key.ptr == nil then
+				error(string.format("JSON %s has no keys", self:type()), 2)
+			end
+			return ffi.string(key.ptr, key.len), new_node(self.doc, tonumber(gh.json_child(self.doc, self.index, i - 1)))
+		end
+	end
+end
+
+---Converts the node to Lua values like `json.decode`, but with numbers as decimals.
+---@return any
+function Node:value()
+	local node_type = self:type()
+	if node_type == "null" then
+		return nil
+	elseif node_type == "boolean" then
+		return self:boolean()
+	elseif node_type == "number" then
+		return self:decimal()
+	elseif node_type == "string" then
+		return self:string()
+	elseif node_type == "array" then
+		local t = {}
+		for i, item in self:ipairs() do
+			t[i] = item:value()
+		end
+		return t
+	else
+		local t = {}
+		for key, member in self:pairs() do
+			t[key] = member:value()
+		end
+		return t
+	end
+end
+
+---Parses `x` natively, returning the root node. Faster than `json.decode` for large payloads
+---which are only partly read, and lossless for numbers.
+---@param x string
+---@return JsonNode
+function M.parse(x)
+	local doc = ffi.gc(gh.json_parse({ ptr = x, len = #x }), gh.json_free)
+	local err = gh.json_error(doc)
+	if err ~= nil then
+		error("JSON parse failed: " .. ffi.string(err), 2)
+	end
+	return new_node(doc, 0)
+end
+
 return M
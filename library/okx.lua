@@ -4,6 +4,7 @@ local util = require("util")
 local decimal = require("decimal")
 local gh = require("gh")
 local json = require("json")
+local orderbook_parser = require("orderbook")
 local send = require("send")
 
 local function extract_data(payload)
@@ -49,18 +50,17 @@ function M.subscribe_orderbook(market, params)
 		error("unsupported market type " .. market_type)
 	end
 
-	local function parse_orderbook(payload)
-		local data = extract_data(payload)
+	local layout =
+		orderbook_parser.layout({ bids = "data.1.bids", asks = "data.1.asks", price = "1", quantity = "2" })
 
-		local orderbook = { bids = {}, asks = {} }
-		for _, v in ipairs(data[1].bids) do
-			table.insert(orderbook.bids, { price = decimal(v[1]), quantity = decimal(v[2]) })
-		end
-		for _, v in ipairs(data[1].asks) do
-			table.insert(orderbook.asks, { price = decimal(v[1]), quantity = decimal(v[2]) })
+	local function parse_orderbook(payload)
+		local orderbook, err = orderbook_parser.parse(payload, layout)
+		if orderbook == nil then
+			-- raises the API error, if any
+			extract_data(payload)
+			error(err)
 		end
-
-		return common.wrap_orderbook(orderbook)
+		return orderbook
 	end
 
 	local req = M.build_request(
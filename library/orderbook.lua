@@ -0,0 +1,118 @@
+local ffi = require("ffi")
+local json = require("json")
+local common = require("common")
+
+---@type any
+local gh = {}
+
+local M = {}
+
+function M.cdef()
+	ffi.cdef([[
+        typedef struct OrderbookLayout OrderbookLayout;
+
+        typedef struct {
+            decimal_t price;
+            decimal_t quantity;
+        } OrderbookLevel;
+
+        typedef struct {
+            const OrderbookLevel *bids;
+            size_t bids_len;
+            size_t bids_cap;
+            const OrderbookLevel *asks;
+            size_t asks_len;
+            size_t asks_cap;
+            const char *error;
+        } FfiOrderbook;
+
+        OrderbookLayout* orderbook_layout_new(LuaStr);
+        void orderbook_layout_free(OrderbookLayout*);
+        const char* orderbook_layout_error(const OrderbookLayout*);
+        FfiOrderbook parse_orderbook(const ResponsePayload*, const OrderbookLayout*);
+        void free_orderbook(FfiOrderbook);
+    ]])
+end
+
+function M.set_clib(clib)
+	gh = clib
+end
+
+---@class OrderbookLayoutSpec
+---@field bids string path of the bid levels from the payload root, e.g. `data.1.bids`
+---@field asks string path of the ask levels from the payload root
+---@field price string path of the price from each level, e.g. `1` or `p`
+---@field quantity string path of the quantity from each level
+
+---Prepares a layout for `parse`. Paths are dotted, and array positions count from 1.
+---@param spec OrderbookLayoutSpec
+---@return ffi.cdata*
+function M.layout(spec)
+	local s = json.encode(spec)
+	local layout = ffi.gc(gh.orderbook_layout_new({ ptr = s, len = #s }), gh.orderbook_layout_free)
+	local err = gh.orderbook_layout_error(layout)
+	if err ~= nil then
+		error("invalid orderbook layout: " .. ffi.string(err), 2)
+	end
+	return layout
+end
+
+---Wraps `len` native levels as an `OrderbookEntry[]`. Entries are copied out of the native
+---array on first access, so that ticks only pay for the levels they read. `owner` keeps the array
+---alive. Iterating and `#` rely on the Lua 5.2 metamethods.
+local function level_array(owner, levels, len)
+	len = tonumber(len)
+	local function iterate(tbl, i)
+		i = i + 1
+		local entry = tbl[i]
+		if entry ~= nil then
+			return i, entry
+		end
+	end
+	return setmetatable({}, {
+		owner = owner,
+		__index = function(tbl, i)
+			if type(i) ~= "number" or i < 1 or i > len or i % 1 ~= 0 then
+				return nil
+			end
+			local entry = {
+				price = ffi.new("decimal_t", levels[i - 1].price),
+				quantity = ffi.new("decimal_t", levels[i - 1].quantity),
+			}
+			rawset(tbl, i, entry)
+			return entry
+		end,
+		__len = function()
+			return len
+		end,
+		__ipairs = function(tbl)
+			return iterate, tbl, 0
+		end,
+		__pairs = function(tbl)
+			return iterate, tbl, 0
+		end,
+	})
+end
+
+---Parses an orderbook payload natively. Bids and asks are sorted from the best price, and are
+---read-only views of the native levels.
+---@param payload ResponsePayload
+---@param layout ffi.cdata* from `layout`
+---@return Orderbook | nil
+---@return string | nil error message if the payload does not match the layout
+function M.parse(payload, layout)
+	local parsed = gh.parse_orderbook(payload, layout)
+	if parsed.error ~= nil then
+		local err = ffi.string(parsed.error)
+		gh.free_orderbook(parsed)
+		return nil, err
+	end
+	parsed = ffi.gc(parsed, gh.free_orderbook)
+	local orderbook = {
+		bids = level_array(parsed, parsed.bids, parsed.bids_len),
+		asks = level_array(parsed, parsed.asks, parsed.asks_len),
+	}
+	return common.wrap_orderbook(orderbook)
+end
+
+return M
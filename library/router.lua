@@ -62,6 +62,23 @@ function M.register(req, callback)
 	return extractor
 end
 
+---Removes the route registered with `register`. Pair this with `gh._unsubscribe` to stop polling.
+---@param req Request
+function M.unregister(req)
+	local routes, recent_payloads, recent_callback_results = get_strategy_locals()
+	local url_and_env_suffix = req.url
+	if req.env_suffix then
+		url_and_env_suffix = req.url .. ":" .. req.env_suffix
+	end
+	local route = routes[url_and_env_suffix]
+	if route == nil then
+		return
+	end
+	routes[url_and_env_suffix] = nil
+	recent_payloads[url_and_env_suffix] = nil
+	recent_callback_results[route[3]] = nil
+end
+
 ---@return table | nil
 ---@return Extractor<any> | nil
 ---@return any
@@ -80,7 +97,7 @@ local function next()
 		end
 		if payload == nil then
 			payload = context.yield(function(ev)
-				if ev.kind == "fetcher" then
+				if ev.kind == "fetcher" or ev.kind == "ws" then
 					url_and_env_suffix = ev.response_payload.url
 					if ev.response_payload.env_suffix then
 						url_and_env_suffix = ev.response_payload.url .. ":" .. ev.response_payload.env_suffix
@@ -7,12 +7,21 @@ local M = {}
 function M.send(payload)
 	local token = gh._send(payload)
 	timer.pause()
-	local ret = context.yield(function(ev)
-		if ev.kind == "send_response" and ev.token == token then
-			return ev.response_payload
+	local kind, ret = context.yield(function(ev)
+		if
+			(ev.kind == "send_response" or ev.kind == "rate_limited" or ev.kind == "trading_halted")
+			and ev.token == token
+		then
+			return ev.kind, ev.response_payload
 		end
 	end)
 	timer.resume()
+	if kind == "rate_limited" then
+		error(string.format("send to %s rate limited", payload.url), 2)
+	end
+	if kind == "trading_halted" then
+		error(string.format("send to %s rejected: trading halted", payload.url), 2)
+	end
 	if ret.error then
 		error(string.format("send to %s failed: status %s, content %s", payload.url, ret.status, ret.content), 2)
 	end
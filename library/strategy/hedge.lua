@@ -98,6 +98,8 @@ local function hedge(transposed_config, ignore_hedge_safeguard)
 	local taker_price_unit = config.taker_price_unit or {}
 	local taker_quantity_unit = config.taker_quantity_unit or {}
 
+	gh.register_gauge("grasshopper_hedge_net_position", "Net position of each hedged currency", { "currency" })
+
 	router.on(function(x, extractor)
 		local position_error = false
 		for currency in pairs(transposed_config) do
@@ -125,6 +127,7 @@ local function hedge(transposed_config, ignore_hedge_safeguard)
 			end
 
 			local net_position = unadjusted_net_position + position_offset[currency]
+			gh.set_gauge("grasshopper_hedge_net_position", net_position, { currency = currency })
 			if taker_price_unit[currency] == nil and taker_quantity_unit[currency] == nil then
 				taker_price_unit[currency], taker_quantity_unit[currency] =
 					util.orderbook_units(taker_orderbooks[currency](x))
@@ -221,19 +224,13 @@ local function hedge(transposed_config, ignore_hedge_safeguard)
 							currency,
 							taker_bases[currency] or "USDT"
 						)
+						local quantity_unit = taker_quantity_unit[currency]
 						local amount = net_position_sign
-							* (
-								((net_position:abs()) / taker_quantity_unit[currency]):round_to_decimals(0)
-								* taker_quantity_unit[currency]
-							):min(
-								(max_order_size / taker_quantity_unit[currency]):floor_to_decimals(0)
-									* taker_quantity_unit[currency]
-							)
+							* net_position:abs():round_to_step(quantity_unit):min(max_order_size:floor_to_step(quantity_unit))
 						if amount:abs() > decimal(0) then
 							local order = taker_exchanges[currency].limit_order(
 								taker_market,
-								(limit_price / taker_price_unit[currency]):round_to_decimals(0)
-									* taker_price_unit[currency],
+								limit_price:round_to_step(taker_price_unit[currency]),
 								amount,
 								params
 							)
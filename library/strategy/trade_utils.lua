@@ -13,45 +13,19 @@ local function price_exists(orderbook_units, test_price)
 	return false
 end
 
-M.upbit_stoppers = {
-	{ decimal("0.1"), decimal("0.0001") },
-	{ decimal("1"), decimal("0.001") },
-	{ decimal("10"), decimal("0.01") },
-	{ decimal("100"), decimal("1") },
-	{ decimal("1000"), decimal("1") },
-	{ decimal("10000"), decimal("5") },
-	{ decimal("100000"), decimal("10") },
-	{ decimal("500000"), decimal("50") },
-	{ decimal("1000000"), decimal("100") },
-	{ decimal("2000000"), decimal("500") },
-	{ decimal("9999999999"), decimal("1000") },
-}
+---Tick table of Upbit, for `krw_price_unit`. Now the exchange name of `gh.price_unit`.
+M.upbit_stoppers = "upbit"
 
-M.bithumb_stoppers = {
-	{ decimal("1"), decimal("0.001") },
-	{ decimal("10"), decimal("0.01") },
-	{ decimal("100"), decimal("1") },
-	-- { decimal("5000"), decimal("1") },
-	{ decimal("10000"), decimal("5") },
-	{ decimal("50000"), decimal("10") },
-	{ decimal("100000"), decimal("50") },
-	{ decimal("500000"), decimal("100") },
-	{ decimal("1000000"), decimal("500") },
-	{ decimal("9999999999"), decimal("1000") },
-}
+---Tick table of Bithumb, for `krw_price_unit`. Now the exchange name of `gh.price_unit`.
+M.bithumb_stoppers = "bithumb"
 
----@param stoppers Decimal[][]
+---@deprecated Use `gh.price_unit`.
+---@param stoppers string
 ---@param price Decimal
 ---@param stop_on_equal boolean
 ---@return Decimal
 function M.krw_price_unit(stoppers, price, stop_on_equal)
-	for _, v in ipairs(stoppers) do
-		local upper, unit = unpack(v)
-		if price < upper or price == upper and stop_on_equal then
-			return unit
-		end
-	end
-	error("no suitable stopper found, price " .. tostring(price) .. " too big")
+	return gh.price_unit(stoppers, price, stop_on_equal)
 end
 
 ---@param orderbook Orderbook
@@ -83,9 +57,9 @@ function M.smart_price_bid(
 	end
 	if force_maker then
 		if is_upbit then
-			price_unit = M.krw_price_unit(M.upbit_stoppers, price, true)
+			price_unit = gh.price_unit("upbit", price, true)
 		elseif is_bithumb then
-			price_unit = M.krw_price_unit(M.bithumb_stoppers, price, true)
+			price_unit = gh.price_unit("bithumb", price, true)
 		end
 		if orderbook.asks[1].price > orderbook.bids[1].price + price_unit then
 			price = orderbook.bids[1].price + price_unit
@@ -95,9 +69,9 @@ function M.smart_price_bid(
 	end
 	while premium_fun(price) < premium_target do
 		if is_upbit then
-			price_unit = M.krw_price_unit(M.upbit_stoppers, price, true)
+			price_unit = gh.price_unit("upbit", price, true)
 		elseif is_bithumb then
-			price_unit = M.krw_price_unit(M.bithumb_stoppers, price, true)
+			price_unit = gh.price_unit("bithumb", price, true)
 		end
 		price = price - search_price_unit
 	end
@@ -143,9 +117,9 @@ function M.smart_price_ask(
 	end
 	if force_maker then
 		if is_upbit then
-			price_unit = M.krw_price_unit(M.upbit_stoppers, price, false)
+			price_unit = gh.price_unit("upbit", price, false)
 		elseif is_bithumb then
-			price_unit = M.krw_price_unit(M.bithumb_stoppers, price, false)
+			price_unit = gh.price_unit("bithumb", price, false)
 		end
 		if orderbook.bids[1].price < orderbook.asks[1].price - price_unit then
 			price = orderbook.asks[1].price - price_unit
@@ -155,9 +129,9 @@ function M.smart_price_ask(
 	end
 	while premium_fun(price) < premium_target do
 		if is_upbit then
-			price_unit = M.krw_price_unit(M.upbit_stoppers, price, false)
+			price_unit = gh.price_unit("upbit", price, false)
 		elseif is_bithumb then
-			price_unit = M.krw_price_unit(M.bithumb_stoppers, price, false)
+			price_unit = gh.price_unit("bithumb", price, false)
 		end
 		price = price + search_price_unit
 	end
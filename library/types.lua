@@ -12,6 +12,21 @@
 ---@field floor_to_decimals fun(self: Decimal, decimals: integer): Decimal
 ---@field ceil_to_decimals fun(self: Decimal, decimals: integer): Decimal
 ---@field round_to_decimals fun(self: Decimal, decimals: integer): Decimal
+---@field sqrt fun(self: Decimal): Decimal
+---@field ln fun(self: Decimal): Decimal
+---@field exp fun(self: Decimal): Decimal
+---@field trunc fun(self: Decimal): Decimal
+---@field signum fun(self: Decimal): Decimal
+---@field is_zero fun(self: Decimal): boolean
+---@field scale fun(self: Decimal): integer
+---@field rescale fun(self: Decimal, scale: integer): Decimal
+---@field normalize fun(self: Decimal): Decimal
+---@field to_number fun(self: Decimal): number lossy conversion, e.g. for plotting
+---@field floor_to_step fun(self: Decimal, step: Decimal): Decimal rounds toward zero
+---@field ceil_to_step fun(self: Decimal, step: Decimal): Decimal rounds away from zero
+---@field round_to_step fun(self: Decimal, step: Decimal, mode: RoundingMode | nil): Decimal
+
+---@alias RoundingMode "half_even" | "half_away_from_zero" | "half_toward_zero" | "toward_zero" | "away_from_zero" | "floor" | "ceil"
 
 ---@alias MarketType "spot" | "swap"
 ---@alias Market string
@@ -42,6 +57,22 @@
 ---@field sign string | nil
 ---@field primary_only boolean | nil
 ---@field env_suffix string | nil
+---@field timeout_ms integer | nil total timeout of each attempt
+---@field retries integer | nil number of retries with exponential backoff on `retry_on_status` or network errors, which are retried for POST and PATCH only if not connected
+---@field retry_on_status integer[] | nil
+---@field http_version "1.0" | "1.1" | "2" | nil
+---@field rate_limit_key string | nil rate limit bucket to use instead of the URL host
+---@field weight number | nil rate limit weight, 1 by default
+
+---@class RateLimitConfig
+---@field key string URL host or `rate_limit_key` of requests
+---@field limit number weight allowed per interval
+---@field interval_ms number
+---@field fail_fast boolean | nil fail sends exceeding the limit instead of delaying them
+
+---@class WsRequest
+---@field url string
+---@field subscribe string[] | nil
 
 ---@class Order
 ---@field price Decimal | nil
@@ -5,15 +5,21 @@ package.path = package.path .. ";library/?.lua;scripts/?.lua"
 local ffi = require("ffi")
 local gh = require("gh")
 local decimal = require("decimal")
+local json = require("json")
+local orderbook = require("orderbook")
 local executor = require("executor")
 
 gh.cdef()
 decimal.cdef()
+json.cdef()
+orderbook.cdef()
 
 local grasshopper = ffi.load("grasshopper")
 
 gh.set_clib(grasshopper)
 decimal.set_clib(grasshopper)
+json.set_clib(grasshopper)
+orderbook.set_clib(grasshopper)
 
 gh.initialize()
 
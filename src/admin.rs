@@ -0,0 +1,250 @@
+//! Admin HTTP API, served on `GRASSHOPPER_ADMIN_ADDR`(`127.0.0.1:8001` by default) apart from
+//! `/metrics`, so that it is not exposed along with the metrics.
+//!
+//! - `GET /strategies`: loaded strategies and the status of their coroutines
+//! - `GET /subscriptions`: polling subscriptions with their period, last status and latency
+//! - `POST /restart`: restarts every strategy, or only `?strategy=<name>`
+//! - `PUT /log-filter`: replaces the log filter with the directives in the body, e.g.
+//!   `info,grasshopper=trace`, of both sinks or only `?sink=console` or `?sink=file`
+//! - `GET /halt`, `PUT /halt`: reads or sets(`true` or `false` as the plain text body, e.g.
+//!   `curl -X PUT -d true`) the trading halt, during which sends other than GETs are answered with
+//!   a `trading_halted` event instead of being sent
+
+use std::{
+    collections::BTreeMap,
+    env::var,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    routing::{get, post, put},
+    Json, Router,
+};
+use eyre::Context;
+use grasshopper_macros::lua_export;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    event::{restart, restart_strategies, RequestPayload, ResponsePayload},
+    fetch_aggregator::{FetchAggregator, DEFAULT_FETCH_AGGREGATOR},
+    LuaStr,
+};
+
+/// Coroutine status of each loaded strategy, as reported by the executor.
+static STRATEGY_STATUS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+static TRADING_HALTED: AtomicBool = AtomicBool::new(false);
+
+/// Log sink whose filter is replaced by `PUT /log-filter`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogSink {
+    Console,
+    File,
+}
+
+type LogFilterReloader = Box<dyn Fn(Option<LogSink>, &str) -> eyre::Result<()> + Send + Sync>;
+
+static LOG_FILTER_RELOADER: OnceLock<LogFilterReloader> = OnceLock::new();
+
+/// Sets how `PUT /log-filter` applies new filter directives to a log sink, or every sink if
+/// [`None`].
+pub(crate) fn set_log_filter_reloader(
+    reloader: impl Fn(Option<LogSink>, &str) -> eyre::Result<()> + Send + Sync + 'static,
+) {
+    if LOG_FILTER_RELOADER.set(Box::new(reloader)).is_err() {
+        panic!("log filter reloader already set");
+    }
+}
+
+/// Records the coroutine status of a strategy, or forgets the strategy if `status` is empty.
+#[lua_export]
+pub extern "C-unwind" fn report_strategy_status(strategy_name: LuaStr, status: LuaStr) {
+    let strategy_name = unsafe { strategy_name.as_str() };
+    let status = unsafe { status.as_str() };
+    let mut statuses = STRATEGY_STATUS.lock().unwrap();
+    if status.is_empty() {
+        statuses.remove(strategy_name);
+    } else {
+        statuses.insert(strategy_name.to_string(), status.to_string());
+    }
+}
+
+/// Answers a send which would change the exchange state with a 503 `trading halted` response
+/// while trading is halted, which is delivered as a `trading_halted` event. Returns [`None`] if
+/// trading is not halted or the request is a GET, so that strategies can still read balances and
+/// orders.
+pub(crate) fn respond_halted(payload: &RequestPayload) -> Option<ResponsePayload> {
+    if !TRADING_HALTED.load(Ordering::Relaxed) || payload.method == Method::GET {
+        return None;
+    }
+    warn!(%payload.url, %payload.method, "trading halted: request not sent");
+    Some(
+        ResponsePayload::from_string(&payload.url, "trading halted".to_string())
+            .with_status(StatusCode::SERVICE_UNAVAILABLE.as_u16())
+            .with_env_suffix(payload.env_suffix.clone()),
+    )
+}
+
+#[derive(Serialize)]
+struct StrategyInfo {
+    name: String,
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct RestartParams {
+    strategy: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LogFilterParams {
+    sink: Option<LogSink>,
+}
+
+async fn strategies() -> Json<Vec<StrategyInfo>> {
+    let statuses = STRATEGY_STATUS.lock().unwrap();
+    Json(
+        statuses
+            .iter()
+            .map(|(name, status)| StrategyInfo {
+                name: name.clone(),
+                status: status.clone(),
+            })
+            .collect(),
+    )
+}
+
+async fn subscriptions() -> Json<impl Serialize> {
+    Json(
+        DEFAULT_FETCH_AGGREGATOR
+            .get_or_init(FetchAggregator::new)
+            .subscriptions()
+            .await,
+    )
+}
+
+async fn restart_handler(Query(params): Query<RestartParams>) -> (StatusCode, String) {
+    match params.strategy {
+        Some(strategy) => {
+            if !STRATEGY_STATUS.lock().unwrap().contains_key(&strategy) {
+                return (
+                    StatusCode::NOT_FOUND,
+                    format!("unknown strategy {strategy}"),
+                );
+            }
+            info!(strategy, "restart requested from the admin API");
+            restart_strategies(&[strategy]).await;
+        }
+        None => {
+            info!("restart requested from the admin API");
+            restart().await;
+        }
+    }
+    (StatusCode::ACCEPTED, String::new())
+}
+
+async fn set_log_filter(
+    Query(params): Query<LogFilterParams>,
+    directives: String,
+) -> (StatusCode, String) {
+    let Some(reloader) = LOG_FILTER_RELOADER.get() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "log filter cannot be changed".to_string(),
+        );
+    };
+    match reloader(params.sink, directives.trim()) {
+        Ok(()) => {
+            info!(directives, sink = ?params.sink, "log filter changed from the admin API");
+            (StatusCode::OK, String::new())
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, format!("{e:#}")),
+    }
+}
+
+async fn halted() -> Json<bool> {
+    Json(TRADING_HALTED.load(Ordering::Relaxed))
+}
+
+async fn set_halted(body: String) -> (StatusCode, String) {
+    let Ok(halted) = body.trim().parse::<bool>() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("expected true or false, got {body:?}"),
+        );
+    };
+    if TRADING_HALTED.swap(halted, Ordering::Relaxed) != halted {
+        if halted {
+            warn!("trading halted from the admin API");
+        } else {
+            warn!("trading resumed from the admin API");
+        }
+    }
+    (StatusCode::OK, String::new())
+}
+
+/// Serves the admin API on `GRASSHOPPER_ADMIN_ADDR`, or `127.0.0.1:8001` if not set.
+pub(crate) async fn admin_server() -> eyre::Result<()> {
+    let router = Router::new()
+        .route("/strategies", get(strategies))
+        .route("/subscriptions", get(subscriptions))
+        .route("/restart", post(restart_handler))
+        .route("/log-filter", put(set_log_filter))
+        .route("/halt", get(halted).put(set_halted));
+
+    let addr = var("GRASSHOPPER_ADMIN_ADDR").unwrap_or_else(|_| "127.0.0.1:8001".to_string());
+    let addr = addr
+        .parse()
+        .with_context(|| format!("invalid admin server address {addr}"))?;
+    axum::Server::bind(&addr)
+        .serve(router.into_make_service())
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use axum::http::StatusCode;
+
+    use super::{respond_halted, set_halted, TRADING_HALTED};
+    use crate::event::{Event, RequestPayload};
+
+    fn payload(method: &str, sign: bool) -> RequestPayload {
+        serde_json::from_str(&format!(
+            r#"{{"url":"https://api.binance.com/api/v3/order","method":"{method}","sign":{sign}}}"#
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn halt_blocks_only_non_get_sends() {
+        assert_eq!(
+            set_halted("yes".to_string()).await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert!(!TRADING_HALTED.load(Ordering::Relaxed));
+        assert_eq!(set_halted("true\n".to_string()).await.0, StatusCode::OK);
+        assert!(TRADING_HALTED.load(Ordering::Relaxed));
+        assert!(respond_halted(&payload("GET", true)).is_none());
+        assert!(respond_halted(&payload("GET", false)).is_none());
+        for method in ["POST", "DELETE", "PUT"] {
+            let response = respond_halted(&payload(method, true)).unwrap();
+            let record = Event::new("trading_halted", response, None).to_record(0);
+            assert_eq!(record.status, 503);
+            assert!(record.error);
+            assert_eq!(record.content.as_deref(), Some("trading halted"));
+        }
+        assert_eq!(set_halted("false".to_string()).await.0, StatusCode::OK);
+        assert!(respond_halted(&payload("POST", true)).is_none());
+    }
+}
@@ -0,0 +1,161 @@
+//! Time source of the strategies.
+//!
+//! The clock is virtual while replaying or if `GRASSHOPPER_VIRTUAL_CLOCK` is set. Virtual time
+//! stands still until it is advanced, by the timestamps of replayed events or by
+//! [`advance_clock`], and timers scheduled with `schedule_timer` fire on it instead of the
+//! runtime.
+
+use std::{
+    env::var,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use grasshopper_macros::lua_export;
+use once_cell::sync::Lazy;
+use rust_decimal::Decimal;
+use tracing::info;
+
+use crate::{lua_decimal::FfiDecimal, replay};
+
+static CLOCK: Lazy<Clock> = Lazy::new(|| {
+    if replay::is_replaying() || var("GRASSHOPPER_VIRTUAL_CLOCK").is_ok() {
+        info!("using virtual clock");
+        Clock::Virtual(Mutex::new(VirtualTime {
+            origin: None,
+            now: Duration::ZERO,
+        }))
+    } else {
+        Clock::Real {
+            start: Instant::now(),
+        }
+    }
+});
+
+enum Clock {
+    Real { start: Instant },
+    Virtual(Mutex<VirtualTime>),
+}
+
+struct VirtualTime {
+    /// Epoch time of the first advance, from which monotonic time is measured.
+    origin: Option<Duration>,
+    /// Time since the Unix epoch. Stays at zero until the first advance.
+    now: Duration,
+}
+
+impl Clock {
+    /// Returns the monotonic time elapsed since the clock was created.
+    fn monotonic(&self) -> Duration {
+        match self {
+            Clock::Real { start } => start.elapsed(),
+            Clock::Virtual(time) => {
+                let time = time.lock().unwrap();
+                time.now - time.origin.unwrap_or(time.now)
+            }
+        }
+    }
+
+    /// Returns the time since the Unix epoch.
+    fn epoch(&self) -> Duration {
+        match self {
+            Clock::Real { .. } => SystemTime::UNIX_EPOCH
+                .elapsed()
+                .expect("timestamp before the Unix epoch"),
+            Clock::Virtual(time) => time.lock().unwrap().now,
+        }
+    }
+}
+
+pub(crate) fn is_virtual() -> bool {
+    matches!(*CLOCK, Clock::Virtual(_))
+}
+
+/// Switches the tests to the virtual clock, serializing the ones which share its time and the
+/// virtual timers.
+#[cfg(test)]
+pub(crate) fn lock_virtual() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let guard = LOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    std::env::set_var("GRASSHOPPER_VIRTUAL_CLOCK", "1");
+    assert!(is_virtual(), "real clock already in use");
+    guard
+}
+
+/// Returns the current time since the Unix epoch.
+pub(crate) fn epoch() -> Duration {
+    CLOCK.epoch()
+}
+
+/// Moves the virtual clock forward to `epoch`. Moving backward is ignored, so that the clock
+/// stays monotonic.
+pub(crate) fn advance_to(epoch: Duration) {
+    let Clock::Virtual(time) = &*CLOCK else {
+        panic!("cannot advance the real clock");
+    };
+    let mut time = time.lock().unwrap();
+    time.origin.get_or_insert(epoch);
+    time.now = time.now.max(epoch);
+}
+
+/// Milliseconds elapsed since the first call, with fractions.
+#[lua_export]
+pub extern "C-unwind" fn millis() -> FfiDecimal {
+    (Decimal::from(CLOCK.monotonic().as_nanos() as u64) / Decimal::new(1_000_000, 0)).into()
+}
+
+/// Milliseconds since the Unix epoch, with fractions.
+#[lua_export]
+pub extern "C-unwind" fn epoch_millis() -> FfiDecimal {
+    (Decimal::from(CLOCK.epoch().as_nanos() as u64) / Decimal::new(1_000_000, 0)).into()
+}
+
+#[lua_export]
+pub extern "C-unwind" fn epoch_nanos() -> FfiDecimal {
+    Decimal::from(CLOCK.epoch().as_nanos() as u64).into()
+}
+
+/// Outcome of [`advance_clock`].
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockStatus {
+    Ok = 0,
+    /// The clock is real, so it cannot be advanced.
+    RealClock = 1,
+    /// The time is negative or too far in the future.
+    OutOfRange = 2,
+}
+
+/// Advances the virtual clock to `epoch_millis` milliseconds since the Unix epoch. Timers due
+/// until then fire on the following `next_event` calls.
+#[lua_export]
+pub extern "C-unwind" fn advance_clock(epoch_millis: FfiDecimal) -> ClockStatus {
+    if !is_virtual() {
+        return ClockStatus::RealClock;
+    }
+    let Some(epoch_nanos) = Decimal::from(epoch_millis)
+        .checked_mul(Decimal::new(1_000_000, 0))
+        .and_then(|x| u64::try_from(x.trunc()).ok())
+    else {
+        return ClockStatus::OutOfRange;
+    };
+    advance_to(Duration::from_nanos(epoch_nanos));
+    ClockStatus::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_clock_rejects_out_of_range_times() {
+        let _guard = lock_virtual();
+        assert_eq!(
+            advance_clock(Decimal::NEGATIVE_ONE.into()),
+            ClockStatus::OutOfRange
+        );
+        assert_eq!(advance_clock(Decimal::MAX.into()), ClockStatus::OutOfRange);
+    }
+}
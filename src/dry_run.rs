@@ -0,0 +1,89 @@
+//! Dry-run mode for checking the orders of a strategy without placing them.
+//!
+//! If `GRASSHOPPER_DRY_RUN` is set, sends which are not GET or are signed are built(and signed)
+//! as usual, but logged instead of being sent. They are answered with the content of
+//! `GRASSHOPPER_DRY_RUN_RESPONSE`, or `{}` if not set. Fetchers are not affected.
+
+use std::env::var;
+
+use once_cell::sync::Lazy;
+use reqwest::Method;
+use tracing::{error, info};
+
+use crate::event::{RequestPayload, ResponsePayload};
+
+/// Canned response content, if dry-running.
+static DRY_RUN_RESPONSE: Lazy<Option<String>> = Lazy::new(|| {
+    var("GRASSHOPPER_DRY_RUN").ok()?;
+    let response = var("GRASSHOPPER_DRY_RUN_RESPONSE").unwrap_or_else(|_| "{}".to_string());
+    info!(response, "dry run enabled");
+    Some(response)
+});
+
+/// Logs and answers a send which would change the exchange state. Returns [`None`] if dry run
+/// is disabled or the request is an unsigned GET.
+pub(crate) fn respond(payload: &RequestPayload) -> Option<ResponsePayload> {
+    respond_with(DRY_RUN_RESPONSE.as_ref()?, payload)
+}
+
+fn respond_with(response: &str, payload: &RequestPayload) -> Option<ResponsePayload> {
+    if payload.method == Method::GET && payload.sign != Some(true) {
+        return None;
+    }
+    let req = match payload.clone().into_async_reqwest() {
+        Ok(x) => x,
+        Err(e) => {
+            error!(%payload.url, %payload.method, "dry run: {e:?}");
+            return Some(ResponsePayload::new_error());
+        }
+    };
+    // header values are omitted as they may contain credentials
+    let headers = req.headers().keys().map(|x| x.as_str()).collect::<Vec<_>>();
+    let body = req
+        .body()
+        .and_then(|x| x.as_bytes())
+        .map(String::from_utf8_lossy);
+    info!(
+        method = %req.method(),
+        url = %req.url(),
+        ?headers,
+        body = body.as_deref(),
+        "dry run: request not sent"
+    );
+    Some(
+        ResponsePayload::from_string(&payload.url, response.to_string())
+            .with_env_suffix(payload.env_suffix.clone()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::respond_with;
+    use crate::event::RequestPayload;
+
+    const RESPONSE: &str = r#"{"dry_run":true}"#;
+
+    fn payload(method: &str, sign: bool) -> RequestPayload {
+        let url = "https://api.example.com/api/v3/order";
+        serde_json::from_str(&format!(
+            r#"{{"url":"{url}","method":"{method}","body":"symbol=BTCUSDT","sign":{sign}}}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn unsigned_gets_pass_through() {
+        assert!(respond_with(RESPONSE, &payload("GET", false)).is_none());
+    }
+
+    #[test]
+    fn state_changing_sends_get_the_canned_response() {
+        for method in ["POST", "PUT", "DELETE"] {
+            let response = respond_with(RESPONSE, &payload(method, false)).unwrap();
+            assert_eq!(response.url(), "https://api.example.com/api/v3/order");
+            assert_eq!(response.content(), Some(RESPONSE.as_bytes()));
+        }
+        // whether or not signing succeeds, a signed GET is answered without being sent
+        assert!(respond_with(RESPONSE, &payload("GET", true)).is_some());
+    }
+}
@@ -7,26 +7,39 @@ use std::{
     num::NonZeroU64,
     ptr::null_mut,
     rc::Rc,
+    slice,
     sync::atomic::{AtomicU64, Ordering},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use eyre::Context;
 use grasshopper_macros::lua_export;
-use reqwest::{header::HeaderName, Client, Method, Response, Url};
+use reqwest::{header::HeaderName, Client, Method, Response, Url, Version};
 use serde::{Deserialize, Deserializer};
 use tokio::{
     runtime::Runtime,
     signal::unix::{signal, SignalKind},
     sync::{mpsc, Mutex},
 };
-use tracing::info;
+use tracing::{debug, info};
 
 use crate::{
+    admin, clock, dry_run,
     fetch_aggregator::{FetchAggregator, DEFAULT_FETCH_AGGREGATOR},
+    logging::current_script_name,
+    metrics::HttpLabels,
+    paper_trading,
+    rate_limit::{self, RateLimited},
+    replay::{self, EventRecord},
+    timer,
+    ws_subscriber::{WsSubscriptions, DEFAULT_WS_SUBSCRIPTIONS},
     LuaStr, RUNTIME_HANDLE,
 };
 
+/// Delay before the first retry of a request, doubled on each next retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
 static QUEUE_TX: Mutex<Option<mpsc::Sender<Event>>> = Mutex::const_new(None);
 static QUEUE_RX: Mutex<Option<mpsc::Receiver<Event>>> = Mutex::const_new(None);
 static LAST_TOKEN: AtomicU64 = AtomicU64::new(1);
@@ -61,17 +74,59 @@ pub(crate) fn install(rt: &Runtime) {
     });
 }
 
-pub(crate) async fn restart() {
+/// Issues a new token to match [`Event`]s with their requests.
+pub(crate) fn next_token() -> NonZeroU64 {
+    NonZeroU64::new(LAST_TOKEN.fetch_add(1, Ordering::Relaxed)).unwrap()
+}
+
+pub(crate) async fn push_event(ev: Event) {
     QUEUE_TX
         .lock()
         .await
         .as_mut()
         .unwrap()
-        .send(Event::new("signal", ResponsePayload::new_restart(), None))
+        .send(ev)
         .await
         .expect("event queue closed");
 }
 
+/// Replaces the event queue with a fresh one and returns its receiving end.
+#[cfg(test)]
+pub(crate) async fn install_test_queue() -> mpsc::Receiver<Event> {
+    let (tx, rx) = mpsc::channel(256);
+    *QUEUE_TX.lock().await = Some(tx);
+    rx
+}
+
+/// Returns the bytes behind a pointer/length pair, or [`None`] if the pointer is null.
+fn raw_bytes<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    (!ptr.is_null()).then(|| unsafe { slice::from_raw_parts(ptr, len) })
+}
+
+/// Leaks `bytes` into a pointer/length/capacity triple, which is null for [`None`].
+fn into_raw_parts(bytes: Option<Vec<u8>>) -> (*mut u8, usize, usize) {
+    match bytes {
+        Some(mut bytes) => {
+            let ptr = bytes.as_mut_ptr();
+            let len = bytes.len();
+            let cap = bytes.capacity();
+            std::mem::forget(bytes);
+            (ptr, len, cap)
+        }
+        None => (null_mut(), 0, 0),
+    }
+}
+
+pub(crate) async fn restart() {
+    push_event(Event::new("signal", ResponsePayload::new_restart(), None)).await
+}
+
+/// Restarts only `strategies`, while the others keep running.
+pub(crate) async fn restart_strategies(strategies: &[String]) {
+    push_event(Event::new("signal", ResponsePayload::new_restart(), None).with_owners(strategies))
+        .await
+}
+
 #[repr(C)]
 pub struct Event {
     kind: *const u8,
@@ -79,6 +134,12 @@ pub struct Event {
     kind_cap: usize,
     response_payload: *const ResponsePayload,
     token: Option<NonZeroU64>,
+    /// JSON array of strategy names subscribed to the event source, or null if the event is not
+    /// owned by particular strategies. An empty name denotes a subscription made outside of any
+    /// strategy.
+    owners: *mut u8,
+    owners_len: usize,
+    owners_cap: usize,
 }
 
 impl Event {
@@ -98,6 +159,79 @@ impl Event {
             kind_cap,
             response_payload: Rc::into_raw(response_payload),
             token,
+            owners: null_mut(),
+            owners_len: 0,
+            owners_cap: 0,
+        }
+    }
+
+    pub fn with_owners(mut self, owners: &[String]) -> Self {
+        let mut owners = serde_json::to_string(owners).unwrap();
+        let (ptr, len, cap) = unsafe {
+            let slice = owners.as_bytes_mut();
+            let ptr = slice.as_mut_ptr();
+            let len = slice.len();
+            let cap = owners.capacity();
+            std::mem::forget(owners);
+            (ptr, len, cap)
+        };
+        self.owners = ptr;
+        self.owners_len = len;
+        self.owners_cap = cap;
+        self
+    }
+
+    pub(crate) fn to_record(&self, ts: u64) -> EventRecord {
+        let lossy = |ptr, len| raw_bytes(ptr, len).map(|x| String::from_utf8_lossy(x).into_owned());
+        let payload = unsafe { &*self.response_payload };
+        EventRecord {
+            ts,
+            kind: lossy(self.kind, self.kind_len).unwrap_or_default(),
+            token: self.token,
+            owners: raw_bytes(self.owners, self.owners_len)
+                .map(|x| serde_json::from_slice(x).expect("invalid owners")),
+            url: lossy(payload.url, payload.url_len),
+            content: lossy(payload.content, payload.content_len),
+            env_suffix: lossy(payload.env_suffix, payload.env_suffix_len),
+            headers: raw_bytes(payload.headers, payload.headers_len)
+                .map(|x| serde_json::from_slice(x).expect("invalid headers")),
+            status: payload.status,
+            error: payload.error,
+            restart: payload.restart,
+            terminate: payload.terminate,
+        }
+    }
+
+    pub(crate) fn from_record(record: EventRecord) -> Self {
+        let (url, url_len, url_cap) = into_raw_parts(record.url.map(String::into_bytes));
+        let (content, content_len, content_cap) =
+            into_raw_parts(record.content.map(String::into_bytes));
+        let (env_suffix, env_suffix_len, env_suffix_cap) =
+            into_raw_parts(record.env_suffix.map(String::into_bytes));
+        let (headers, headers_len, headers_cap) =
+            into_raw_parts(record.headers.map(|x| serde_json::to_vec(&x).unwrap()));
+        let payload = ResponsePayload {
+            url,
+            url_len,
+            url_cap,
+            content,
+            content_len,
+            content_cap,
+            env_suffix,
+            env_suffix_len,
+            env_suffix_cap,
+            headers,
+            headers_len,
+            headers_cap,
+            status: record.status,
+            error: record.error,
+            restart: record.restart,
+            terminate: record.terminate,
+        };
+        let ev = Self::new(&record.kind, payload, record.token);
+        match record.owners {
+            Some(owners) => ev.with_owners(&owners),
+            None => ev,
         }
     }
 
@@ -110,6 +244,15 @@ impl Event {
     #[no_mangle]
     pub extern "C-unwind" fn free_event(self) {
         unsafe { ResponsePayload::free_response_payload(self.response_payload) };
+        if self.owners_cap > 0 {
+            unsafe {
+                drop(String::from_raw_parts(
+                    self.owners,
+                    self.owners_len,
+                    self.owners_cap,
+                ))
+            };
+        }
     }
 }
 
@@ -127,6 +270,25 @@ pub struct RequestPayload {
     pub(crate) primary_only: bool,
     #[serde(default)]
     pub(crate) env_suffix: Option<String>,
+    /// Total timeout of each attempt, overriding the client default.
+    #[serde(default)]
+    pub(crate) timeout_ms: Option<u64>,
+    /// Number of retries on network errors or `retry_on_status` statuses, waiting 100ms before
+    /// the first retry and twice as long before each next one, up to 2s. Network errors of
+    /// non-idempotent methods(POST, PATCH) are retried only if the connection could not be
+    /// established, since the exchange may have processed a request whose response was lost.
+    /// Beware that retrying them on statuses may still duplicate e.g. order placements.
+    #[serde(default)]
+    pub(crate) retries: Option<u32>,
+    #[serde(default)]
+    pub(crate) retry_on_status: Option<Vec<u16>>,
+    #[serde(default, deserialize_with = "deserialize_http_version")]
+    pub(crate) http_version: Option<Version>,
+    /// Rate limit bucket to take `weight` from. Defaults to the host of `url`.
+    #[serde(default)]
+    pub(crate) rate_limit_key: Option<String>,
+    #[serde(default)]
+    pub(crate) weight: Option<f64>,
 }
 
 fn deserialize_method<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Method, D::Error> {
@@ -143,13 +305,92 @@ fn deserialize_method<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Meth
     }
 }
 
+fn deserialize_http_version<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Version>, D::Error> {
+    let Some(version) = Option::<&str>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    match version {
+        "1.0" => Ok(Some(Version::HTTP_10)),
+        "1.1" => Ok(Some(Version::HTTP_11)),
+        "2" | "2.0" => Ok(Some(Version::HTTP_2)),
+        _ => Err(serde::de::Error::custom(format!(
+            "{version} is not a supported HTTP version"
+        ))),
+    }
+}
+
+// NOTE: (un)subscriptions block on the runtime instead of spawning, so that they are applied in
+// the order of calls, e.g. a reloaded strategy never loses its fresh subscriptions to a stale
+// unsubscription. They are ignored while replaying, as the events come from the record file.
+
 #[lua_export]
 pub extern "C-unwind" fn subscribe_rest_events(payload: LuaStr, period_ms: f64) {
+    if replay::is_replaying() {
+        return;
+    }
     let payload = serde_json::from_str(unsafe { payload.as_str() }).expect("cannot parse payload");
-    RUNTIME_HANDLE.lock().unwrap().as_ref().unwrap().spawn(
+    let subscriber = current_script_name().unwrap_or_default();
+    RUNTIME_HANDLE.lock().unwrap().as_ref().unwrap().block_on(
         DEFAULT_FETCH_AGGREGATOR
             .get_or_init(FetchAggregator::new)
-            .subscribe(payload, Duration::from_secs_f64(period_ms / 1000.0)),
+            .subscribe(
+                payload,
+                Duration::from_secs_f64(period_ms / 1000.0),
+                subscriber,
+            ),
+    );
+}
+
+#[lua_export]
+pub extern "C-unwind" fn unsubscribe_rest_events(payload: LuaStr) {
+    if replay::is_replaying() {
+        return;
+    }
+    let payload = serde_json::from_str(unsafe { payload.as_str() }).expect("cannot parse payload");
+    let subscriber = current_script_name().unwrap_or_default();
+    RUNTIME_HANDLE.lock().unwrap().as_ref().unwrap().block_on(
+        DEFAULT_FETCH_AGGREGATOR
+            .get_or_init(FetchAggregator::new)
+            .unsubscribe(payload, subscriber),
+    );
+}
+
+/// Releases every subscription(REST and WebSocket), timer and log file held by the strategy.
+#[lua_export]
+pub extern "C-unwind" fn unsubscribe_strategy(strategy_name: LuaStr) {
+    let strategy_name = unsafe { strategy_name.as_str() }.to_string();
+    RUNTIME_HANDLE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .unwrap()
+        .block_on(async {
+            DEFAULT_FETCH_AGGREGATOR
+                .get_or_init(FetchAggregator::new)
+                .unsubscribe_all(strategy_name.clone())
+                .await;
+            DEFAULT_WS_SUBSCRIPTIONS
+                .get_or_init(WsSubscriptions::new)
+                .unsubscribe_all(strategy_name.clone())
+                .await;
+        });
+    crate::timer::cancel_all(&strategy_name);
+    crate::strategy_log::close_strategy_log(&strategy_name);
+}
+
+#[lua_export]
+pub extern "C-unwind" fn subscribe_ws_events(payload: LuaStr) {
+    if replay::is_replaying() {
+        return;
+    }
+    let payload = serde_json::from_str(unsafe { payload.as_str() }).expect("cannot parse payload");
+    let subscriber = current_script_name().unwrap_or_default();
+    RUNTIME_HANDLE.lock().unwrap().as_ref().unwrap().block_on(
+        DEFAULT_WS_SUBSCRIPTIONS
+            .get_or_init(WsSubscriptions::new)
+            .subscribe(payload, subscriber),
     );
 }
 
@@ -157,18 +398,18 @@ pub extern "C-unwind" fn subscribe_rest_events(payload: LuaStr, period_ms: f64)
 pub extern "C-unwind" fn next_event() -> Event {
     let rt = RUNTIME_HANDLE.lock().unwrap();
     let rt = rt.as_ref().unwrap();
-    rt.block_on(async move {
+    let ev = rt.block_on(async move {
         let mut guard = QUEUE_RX.lock().await;
         let queue = guard.as_mut().unwrap();
         if let Ok(x) = queue.try_recv() {
             x
+        } else if let Some(x) = timer::next_virtual_event(clock::epoch()) {
+            x
+        } else if let Some(x) = replay::next_event() {
+            x
         } else {
-            fn process_payload(payload: Option<ResponsePayload>) -> Event {
-                Event::new(
-                    "fetcher",
-                    payload.unwrap_or(ResponsePayload::new_terminator()),
-                    None,
-                )
+            fn process_payload((payload, owners): (ResponsePayload, Vec<String>)) -> Event {
+                Event::new("fetcher", payload, None).with_owners(&owners)
             }
             let n = DEFAULT_FETCH_AGGREGATOR
                 .get_or_init(FetchAggregator::new)
@@ -178,7 +419,9 @@ pub extern "C-unwind" fn next_event() -> Event {
                 payload = n => process_payload(payload),
             }
         }
-    })
+    });
+    replay::record(&ev);
+    ev
 }
 
 #[lua_export]
@@ -198,14 +441,35 @@ pub extern "C-unwind" fn send_payload(payload: LuaStr) -> NonZeroU64 {
     thread_local! {
         static LOCAL_CLIENT: Client = new_client(None);
         static ADDR_INDEX: Cell<usize> = Cell::new(0);
-        static LOCAL_ADDR_CLIENTS: RefCell<Option<Vec<Client>>> = RefCell::new(None);
+        static LOCAL_ADDR_CLIENTS: RefCell<Option<Vec<(Client, IpAddr)>>> = RefCell::new(None);
     }
 
     let payload: RequestPayload =
         serde_json::from_str(unsafe { payload.as_str() }).expect("cannot parse payload");
 
-    let client = if payload.primary_only {
-        LOCAL_CLIENT.with(|x| x.clone())
+    if replay::is_replaying() {
+        let token = next_token();
+        debug!(%payload.url, %payload.method, "send answered by replay");
+        replay::expect_response(token);
+        return token;
+    }
+    if let Some((kind, response)) = admin::respond_halted(&payload)
+        .map(|x| ("trading_halted", x))
+        .or_else(|| paper_trading::respond(&payload).map(|x| ("send_response", x)))
+        .or_else(|| dry_run::respond(&payload).map(|x| ("send_response", x)))
+    {
+        let token = next_token();
+        RUNTIME_HANDLE
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .spawn(push_event(Event::new(kind, response, Some(token))));
+        return token;
+    }
+
+    let (client, local_addr) = if payload.primary_only {
+        (LOCAL_CLIENT.with(|x| x.clone()), String::new())
     } else {
         LOCAL_ADDR_CLIENTS.with(|x| {
             if x.borrow().is_none() {
@@ -216,24 +480,31 @@ pub extern "C-unwind" fn send_payload(payload: LuaStr) -> NonZeroU64 {
                         .map(|x| x.parse())
                         .collect::<Result<Vec<_>, _>>()
                         .expect("cannot parse local addresses"),
-                    _ => return LOCAL_CLIENT.with(|x| x.clone()),
+                    _ => return (LOCAL_CLIENT.with(|x| x.clone()), String::new()),
                 };
                 *x.borrow_mut() = Some(
                     local_addresses
                         .into_iter()
-                        .map(|x| new_client(Some(x)))
+                        .map(|x| (new_client(Some(x)), x))
                         .collect(),
                 )
             }
             let clients_len = x.borrow().as_ref().unwrap().len();
             let index = ADDR_INDEX.get();
             ADDR_INDEX.set((index + 1) % clients_len);
-            x.borrow().as_ref().unwrap()[index].clone()
+            let (client, local_addr) = x.borrow().as_ref().unwrap()[index].clone();
+            (client, local_addr.to_string())
         })
     };
+    let labels = HttpLabels::new(
+        "send",
+        &payload.url,
+        &payload.method,
+        &local_addr,
+        vec![current_script_name().unwrap_or_default()],
+    );
 
-    let token = LAST_TOKEN.fetch_add(1, Ordering::Relaxed);
-    let token = NonZeroU64::new(token).unwrap();
+    let token = next_token();
     RUNTIME_HANDLE
         .lock()
         .unwrap()
@@ -243,15 +514,21 @@ pub extern "C-unwind" fn send_payload(payload: LuaStr) -> NonZeroU64 {
             let fut = async {
                 let request_url = payload.url.to_string();
                 let env_suffix = payload.env_suffix.clone();
-                let resp = client.execute(payload.into_async_reqwest()?).await?;
+                let resp = payload.execute(&client, true, &labels).await?;
                 let payload = ResponsePayload::new(&request_url, env_suffix.clone(), resp).await?;
                 Ok::<_, eyre::Report>(payload)
             };
-            let payload = fut.await.unwrap_or_else(|e| {
-                tracing::error!("{e:?}");
-                ResponsePayload::new_error()
-            });
-            let ev = Event::new("send_response", payload, Some(token));
+            let ev = match fut.await {
+                Ok(payload) => Event::new("send_response", payload, Some(token)),
+                Err(e) if e.is::<RateLimited>() => {
+                    tracing::warn!("{e}");
+                    Event::new("rate_limited", ResponsePayload::new_error(), Some(token))
+                }
+                Err(e) => {
+                    tracing::error!("{e:?}");
+                    Event::new("send_response", ResponsePayload::new_error(), Some(token))
+                }
+            };
             QUEUE_TX
                 .lock()
                 .await
@@ -304,8 +581,65 @@ impl RequestPayload {
                     .insert(HeaderName::from_bytes(k.as_bytes())?, v.parse()?);
             }
         }
+        if let Some(timeout_ms) = self.timeout_ms {
+            *req.timeout_mut() = Some(Duration::from_millis(timeout_ms));
+        }
+        if let Some(version) = self.http_version {
+            *req.version_mut() = version;
+        }
         Ok(req)
     }
+
+    /// Returns the rate limit bucket of the request: `rate_limit_key` if set, or the URL host.
+    pub(crate) fn rate_limit_key(&self) -> eyre::Result<String> {
+        match &self.rate_limit_key {
+            Some(key) => Ok(key.clone()),
+            None => Ok(Url::parse(&self.url)?
+                .host_str()
+                .unwrap_or_default()
+                .to_string()),
+        }
+    }
+
+    /// Builds and sends the request, retrying as configured by `retries` and `retry_on_status`.
+    /// Each attempt takes `weight` from the rate limit bucket of the request; if `fail_fast` is
+    /// set and the bucket is configured so, [`RateLimited`] is returned instead of waiting.
+    /// Attempts are recorded in the HTTP metrics with `labels`.
+    pub(crate) async fn execute(
+        &self,
+        client: &Client,
+        fail_fast: bool,
+        labels: &HttpLabels,
+    ) -> eyre::Result<Response> {
+        let rate_limit_key = self.rate_limit_key()?;
+        let mut attempt = 0;
+        loop {
+            rate_limit::acquire(&rate_limit_key, self.weight.unwrap_or(1.0), fail_fast).await?;
+            let req = self.clone().into_async_reqwest()?;
+            let start = Instant::now();
+            let result = client.execute(req).await;
+            labels.observe(start.elapsed(), result.as_ref().ok().map(|x| x.status()));
+            let retriable = match &result {
+                Ok(resp) => self
+                    .retry_on_status
+                    .as_ref()
+                    .is_some_and(|x| x.contains(&resp.status().as_u16())),
+                Err(err) => self.method.is_idempotent() || err.is_connect(),
+            };
+            if !retriable || attempt >= self.retries.unwrap_or(0) {
+                return Ok(result?);
+            }
+            let backoff = (RETRY_BACKOFF * 2u32.saturating_pow(attempt)).min(MAX_RETRY_BACKOFF);
+            attempt += 1;
+            match result {
+                Ok(resp) => {
+                    debug!(%self.url, status = %resp.status(), attempt, ?backoff, "retrying request")
+                }
+                Err(err) => debug!(%self.url, %err, attempt, ?backoff, "retrying request"),
+            }
+            tokio::time::sleep(backoff).await;
+        }
+    }
 }
 
 #[repr(C)]
@@ -320,6 +654,11 @@ pub struct ResponsePayload {
     env_suffix: *mut u8,
     env_suffix_len: usize,
     env_suffix_cap: usize,
+    /// JSON object of response headers, keyed by lowercase header names. Repeated headers are
+    /// joined with `", "`.
+    headers: *mut u8,
+    headers_len: usize,
+    headers_cap: usize,
     status: u16,
     /// Responses on [`send()`] calls: indicates a network error or non-2xx response has been
     /// ocurred.
@@ -339,6 +678,18 @@ impl ResponsePayload {
         let mut url = request_url.to_string();
         let error = !resp.status().is_success();
         let status = resp.status().as_u16();
+        let mut headers = HashMap::<String, String>::new();
+        for (k, v) in resp.headers() {
+            let v = String::from_utf8_lossy(v.as_bytes());
+            headers
+                .entry(k.to_string())
+                .and_modify(|x| {
+                    x.push_str(", ");
+                    x.push_str(&v);
+                })
+                .or_insert_with(|| v.into_owned());
+        }
+        let mut headers = serde_json::to_string(&headers)?;
         let mut content = resp.bytes().await?.to_vec();
         let (url, url_len, url_cap) = unsafe {
             let slice = url.as_bytes_mut();
@@ -365,6 +716,14 @@ impl ResponsePayload {
         } else {
             (null_mut(), 0, 0)
         };
+        let (headers, headers_len, headers_cap) = unsafe {
+            let slice = headers.as_bytes_mut();
+            let ptr = slice.as_mut_ptr();
+            let len = slice.len();
+            let cap = headers.capacity();
+            std::mem::forget(headers);
+            (ptr, len, cap)
+        };
         Ok(ResponsePayload {
             url,
             url_len,
@@ -375,6 +734,9 @@ impl ResponsePayload {
             env_suffix,
             env_suffix_len,
             env_suffix_cap,
+            headers,
+            headers_len,
+            headers_cap,
             status,
             error,
             restart: false,
@@ -412,6 +774,9 @@ impl ResponsePayload {
             env_suffix: null_mut(),
             env_suffix_len: 0,
             env_suffix_cap: 0,
+            headers: null_mut(),
+            headers_len: 0,
+            headers_cap: 0,
             status,
             error,
             restart: false,
@@ -419,6 +784,39 @@ impl ResponsePayload {
         }
     }
 
+    /// Sets the status code, marking the response as failed unless it is 2xx.
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self.error = !(200..300).contains(&status);
+        self
+    }
+
+    pub fn with_env_suffix(mut self, env_suffix: Option<String>) -> Self {
+        if self.env_suffix_cap > 0 {
+            unsafe {
+                drop(String::from_raw_parts(
+                    self.env_suffix,
+                    self.env_suffix_len,
+                    self.env_suffix_cap,
+                ))
+            };
+        }
+        (self.env_suffix, self.env_suffix_len, self.env_suffix_cap) =
+            into_raw_parts(env_suffix.map(String::into_bytes));
+        self
+    }
+
+    /// Returns the URL of the request.
+    pub(crate) fn url(&self) -> &str {
+        raw_bytes(self.url, self.url_len)
+            .map(|x| std::str::from_utf8(x).expect("URL is not a valid UTF-8 sequence"))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn content(&self) -> Option<&[u8]> {
+        raw_bytes(self.content, self.content_len)
+    }
+
     pub const fn new_terminator() -> Self {
         Self {
             url: null_mut(),
@@ -430,6 +828,9 @@ impl ResponsePayload {
             env_suffix: null_mut(),
             env_suffix_len: 0,
             env_suffix_cap: 0,
+            headers: null_mut(),
+            headers_len: 0,
+            headers_cap: 0,
             status: 0,
             error: false,
             restart: false,
@@ -448,6 +849,9 @@ impl ResponsePayload {
             env_suffix: null_mut(),
             env_suffix_len: 0,
             env_suffix_cap: 0,
+            headers: null_mut(),
+            headers_len: 0,
+            headers_cap: 0,
             status: 0,
             error: true,
             restart: true,
@@ -455,6 +859,27 @@ impl ResponsePayload {
         }
     }
 
+    pub const fn new_empty() -> Self {
+        Self {
+            url: null_mut(),
+            url_len: 0,
+            url_cap: 0,
+            content: null_mut(),
+            content_len: 0,
+            content_cap: 0,
+            env_suffix: null_mut(),
+            env_suffix_len: 0,
+            env_suffix_cap: 0,
+            headers: null_mut(),
+            headers_len: 0,
+            headers_cap: 0,
+            status: 0,
+            error: false,
+            restart: false,
+            terminate: false,
+        }
+    }
+
     pub const fn new_error() -> Self {
         Self {
             url: null_mut(),
@@ -466,6 +891,9 @@ impl ResponsePayload {
             env_suffix: null_mut(),
             env_suffix_len: 0,
             env_suffix_cap: 0,
+            headers: null_mut(),
+            headers_len: 0,
+            headers_cap: 0,
             status: 0,
             error: true,
             restart: false,
@@ -492,6 +920,11 @@ impl Drop for ResponsePayload {
                 let content = Vec::from_raw_parts(self.content, self.content_len, self.content_cap);
                 drop(content);
             }
+            if self.headers_cap > 0 {
+                let headers =
+                    String::from_raw_parts(self.headers, self.headers_len, self.headers_cap);
+                drop(headers);
+            }
         }
     }
 }
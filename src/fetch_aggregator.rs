@@ -1,59 +1,292 @@
 use std::{
     cell::RefCell,
     collections::HashMap,
-    sync::{Arc, OnceLock},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
     time::Duration,
 };
 
 use futures::{future::select_all, FutureExt};
-use tokio::sync::Mutex;
+use serde::Serialize;
+use tokio::sync::{Mutex, Notify};
 use tracing::{info, instrument, warn};
 
 use crate::{
     event::{RequestPayload, ResponsePayload},
-    fetcher::Fetcher,
+    fetcher::{Fetcher, FetcherStatus},
+    paper_trading,
 };
 
 pub(crate) static DEFAULT_FETCH_AGGREGATOR: OnceLock<FetchAggregator> = OnceLock::new();
 
+struct Subscription {
+    fetcher: Arc<Fetcher>,
+    /// Number of live subscriptions per subscriber(strategy) name.
+    subscribers: HashMap<String, usize>,
+}
+
+/// Subscription listed by the admin API.
+#[derive(Debug, Serialize)]
+pub struct SubscriptionInfo {
+    url: String,
+    method: String,
+    subscribers: Vec<String>,
+    #[serde(flatten)]
+    status: FetcherStatus,
+}
+
 #[derive(Clone)]
 pub struct FetchAggregator {
-    #[allow(clippy::type_complexity)]
-    fetchers: Arc<Mutex<RefCell<HashMap<RequestPayload, Arc<Fetcher>>>>>,
+    fetchers: Arc<Mutex<RefCell<HashMap<RequestPayload, Subscription>>>>,
+    cursor: Arc<AtomicUsize>,
+    /// Notified when a subscription is added or removed.
+    changed: Arc<Notify>,
 }
 
 impl FetchAggregator {
     pub fn new() -> Self {
         Self {
             fetchers: Arc::new(Mutex::const_new(RefCell::new(HashMap::new()))),
+            cursor: Arc::new(AtomicUsize::new(0)),
+            changed: Arc::new(Notify::new()),
         }
     }
 
     #[instrument(skip(self))]
-    pub async fn subscribe(&self, payload: RequestPayload, period: Duration) {
+    pub async fn subscribe(&self, payload: RequestPayload, period: Duration, subscriber: String) {
         let guard = self.fetchers.lock().await;
         let mut fetchers = guard.borrow_mut();
 
-        fetchers.entry(payload.clone()).or_insert_with(|| {
+        let subscription = fetchers.entry(payload.clone()).or_insert_with(|| {
             info!("new subscription created");
-            Arc::new(Fetcher::new(payload.clone(), period))
+            self.changed.notify_one();
+            Subscription {
+                fetcher: Arc::new(Fetcher::new(payload, period)),
+                subscribers: HashMap::new(),
+            }
+        });
+        *subscription.subscribers.entry(subscriber).or_default() += 1;
+        subscription
+            .fetcher
+            .set_subscribers(subscription.subscribers.keys());
+    }
+
+    /// Releases one subscription of `subscriber`. The fetcher is killed when its last subscriber
+    /// goes away.
+    #[instrument(skip(self))]
+    pub async fn unsubscribe(&self, payload: RequestPayload, subscriber: String) {
+        let guard = self.fetchers.lock().await;
+        let mut fetchers = guard.borrow_mut();
+
+        let Some(subscription) = fetchers.get_mut(&payload) else {
+            warn!("unsubscribing from unknown subscription");
+            return;
+        };
+        match subscription.subscribers.get_mut(&subscriber) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                subscription.subscribers.remove(&subscriber);
+            }
+            None => {
+                warn!("unsubscribing from subscription not owned by the subscriber");
+                return;
+            }
+        }
+        if subscription.subscribers.is_empty() {
+            info!("subscription removed");
+            fetchers.remove(&payload).unwrap().fetcher.kill();
+            self.changed.notify_one();
+        } else {
+            subscription
+                .fetcher
+                .set_subscribers(subscription.subscribers.keys());
+        }
+    }
+
+    /// Releases every subscription of `subscriber`, e.g. when its strategy is reloaded.
+    #[instrument(skip(self))]
+    pub async fn unsubscribe_all(&self, subscriber: String) {
+        let guard = self.fetchers.lock().await;
+        let mut fetchers = guard.borrow_mut();
+
+        fetchers.retain(|payload, subscription| {
+            if subscription.subscribers.remove(&subscriber).is_none() {
+                return true;
+            }
+            if subscription.subscribers.is_empty() {
+                info!(%payload.url, "subscription removed");
+                subscription.fetcher.kill();
+                self.changed.notify_one();
+                false
+            } else {
+                subscription
+                    .fetcher
+                    .set_subscribers(subscription.subscribers.keys());
+                true
+            }
         });
     }
 
-    pub async fn next(&self) -> Option<ResponsePayload> {
-        let mut futs = Vec::new();
-        {
+    /// Lists the active subscriptions, sorted by URL.
+    pub async fn subscriptions(&self) -> Vec<SubscriptionInfo> {
+        let guard = self.fetchers.lock().await;
+        let fetchers = guard.borrow();
+        let mut ret = fetchers
+            .iter()
+            .map(|(payload, subscription)| {
+                let mut subscribers = subscription.subscribers.keys().cloned().collect::<Vec<_>>();
+                subscribers.sort_unstable();
+                SubscriptionInfo {
+                    url: payload.url.clone(),
+                    method: payload.method.to_string(),
+                    subscribers,
+                    status: subscription.fetcher.status(),
+                }
+            })
+            .collect::<Vec<_>>();
+        ret.sort_unstable_by(|a, b| a.url.cmp(&b.url));
+        ret
+    }
+
+    /// Returns the next fetched payload with the names of its subscribers, waiting for a
+    /// subscription if there is none.
+    ///
+    /// Fetchers are polled starting from a rotating offset, so that a strategy with many busy
+    /// subscriptions cannot starve the subscriptions of other strategies.
+    pub async fn next(&self) -> (ResponsePayload, Vec<String>) {
+        loop {
+            let mut futs = Vec::new();
+            {
+                let guard = self.fetchers.lock().await;
+                let fetchers = guard.borrow();
+                for (payload, subscription) in fetchers.iter() {
+                    let payload = payload.clone();
+                    futs.push(
+                        Arc::clone(&subscription.fetcher)
+                            .next()
+                            .map(|x| x.map(|x| (x, payload)))
+                            .boxed_local(),
+                    );
+                }
+            }
+            if futs.is_empty() {
+                self.changed.notified().await;
+                continue;
+            }
+            let offset = self.cursor.fetch_add(1, Ordering::Relaxed) % futs.len();
+            futs.rotate_left(offset);
+            let (response, payload) = tokio::select! {
+                (x, _, _) = select_all(futs) => match x {
+                    Some(x) => x,
+                    None => continue,
+                },
+                // subscriptions added or removed while waiting
+                _ = self.changed.notified() => continue,
+            };
+            paper_trading::observe(&response);
+
             let guard = self.fetchers.lock().await;
             let fetchers = guard.borrow();
-            for fetcher in fetchers.values() {
-                futs.push(Arc::clone(fetcher).next().boxed_local());
-            }
+            let owners = fetchers
+                .get(&payload)
+                .map(|x| x.subscribers.keys().cloned().collect())
+                .unwrap_or_default();
+            return (response, owners);
         }
-        if futs.is_empty() {
-            warn!("no subscription to fetch");
-            tokio::time::sleep(Duration::from_secs(1)).await;
-            return None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::FetchAggregator;
+    use crate::event::RequestPayload;
+
+    fn payload(url: &str) -> RequestPayload {
+        serde_json::from_str(&format!(r#"{{"url":"{url}","method":"GET"}}"#)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn last_unsubscribe_kills_fetcher() {
+        let aggregator = FetchAggregator::new();
+        let payload = payload("http://millis.local/refcount");
+        let period = Duration::from_millis(10);
+        for subscriber in ["a", "a", "b"] {
+            aggregator
+                .subscribe(payload.clone(), period, subscriber.to_string())
+                .await;
         }
-        select_all(futs).await.0
+        let fetcher = {
+            let guard = aggregator.fetchers.lock().await;
+            let fetchers = guard.borrow();
+            Arc::clone(&fetchers[&payload].fetcher)
+        };
+
+        aggregator
+            .unsubscribe(payload.clone(), "a".to_string())
+            .await;
+        aggregator
+            .unsubscribe(payload.clone(), "b".to_string())
+            .await;
+        let subscriptions = aggregator.subscriptions().await;
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].subscribers, ["a"]);
+        assert!(!fetcher.is_killed());
+
+        let (_, owners) = tokio::time::timeout(Duration::from_secs(1), aggregator.next())
+            .await
+            .unwrap();
+        assert_eq!(owners, ["a"]);
+
+        aggregator
+            .unsubscribe(payload.clone(), "a".to_string())
+            .await;
+        assert!(aggregator.subscriptions().await.is_empty());
+        assert!(fetcher.is_killed());
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_all_keeps_shared_fetchers() {
+        let aggregator = FetchAggregator::new();
+        let shared = payload("http://millis.local/shared");
+        let owned = payload("http://millis.local/owned");
+        let period = Duration::from_millis(10);
+        aggregator
+            .subscribe(shared.clone(), period, "a".to_string())
+            .await;
+        aggregator
+            .subscribe(shared.clone(), period, "b".to_string())
+            .await;
+        aggregator.subscribe(owned, period, "a".to_string()).await;
+
+        aggregator.unsubscribe_all("a".to_string()).await;
+        let subscriptions = aggregator.subscriptions().await;
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].url, shared.url);
+        assert_eq!(subscriptions[0].subscribers, ["b"]);
+    }
+
+    #[tokio::test]
+    async fn next_waits_for_subscription() {
+        let aggregator = FetchAggregator::new();
+        let subscribe = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            aggregator
+                .subscribe(
+                    payload("http://millis.local/late"),
+                    Duration::from_millis(10),
+                    "a".to_string(),
+                )
+                .await;
+        };
+        let ((_, owners), ()) = tokio::time::timeout(Duration::from_secs(1), async {
+            tokio::join!(aggregator.next(), subscribe)
+        })
+        .await
+        .unwrap();
+        assert_eq!(owners, ["a"]);
     }
 }
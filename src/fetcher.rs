@@ -1,21 +1,163 @@
 use std::{
     env::var,
+    str::FromStr,
     sync::{Arc, Mutex},
-    time::{Duration, SystemTime},
+    time::Duration,
 };
 
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode, Url,
+};
+use serde::Serialize;
 use tokio::{
     sync::{oneshot, Notify},
-    time::interval,
+    time::{sleep_until, Instant},
+};
+use tracing::{debug, error, info};
+
+use crate::{
+    clock,
+    event::{RequestPayload, ResponsePayload},
+    metrics::{self, FetcherLabels, HttpLabels, FETCHER_PERIOD_GAUGE},
+    paper_trading, rate_limit,
 };
-use tracing::{debug, error};
 
-use crate::event::{RequestPayload, ResponsePayload};
+/// Upper bound of polling periods slowed down by the exchange, unless the requested period is
+/// longer.
+const MAX_PERIOD: Duration = Duration::from_secs(60);
+/// Ratio of used weight above which polls are slowed down.
+const WEIGHT_USAGE_THRESHOLD: f64 = 0.8;
+
+/// Polling period adapted to the rate limit feedback of the exchange.
+///
+/// The period doubles on HTTP 429/418 (or follows `Retry-After` if longer) and grows by half
+/// while weight headers are close to their limit. Otherwise it shrinks by a tenth per response
+/// until it is back to the requested period.
+struct AdaptivePeriod {
+    requested: Duration,
+    current: Duration,
+    /// Bucket to compare `x-mbx-used-weight-1m` with, which is not paired with a limit header.
+    rate_limit_key: Option<String>,
+    /// Host of the request URL, to fall back to the documented Binance limits if the bucket is
+    /// not configured.
+    host: String,
+}
+
+impl AdaptivePeriod {
+    fn new(requested: Duration, rate_limit_key: Option<String>, host: String) -> Self {
+        Self {
+            requested,
+            current: requested,
+            rate_limit_key,
+            host,
+        }
+    }
+
+    fn current(&self) -> Duration {
+        self.current
+    }
+
+    fn observe(&mut self, status: StatusCode, headers: &HeaderMap) {
+        let max_period = MAX_PERIOD.max(self.requested);
+        let previous = self.current;
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::IM_A_TEAPOT {
+            let retry_after = header::<u64>(headers, RETRY_AFTER.as_str())
+                .map(Duration::from_secs)
+                .unwrap_or_default();
+            self.current = (self.current * 2).min(max_period).max(retry_after);
+        } else if self
+            .weight_usage(headers)
+            .is_some_and(|x| x >= WEIGHT_USAGE_THRESHOLD)
+        {
+            self.current = self.current.mul_f64(1.5).min(max_period);
+        } else {
+            self.current = self.current.mul_f64(0.9).max(self.requested);
+        }
+
+        if self.current > previous {
+            info!(%status, period = ?self.current, "slowing down polling");
+        } else if self.current < previous && self.current == self.requested {
+            info!(period = ?self.current, "polling period restored");
+        }
+    }
+
+    /// Returns the ratio of the used weight reported by exchange-specific headers.
+    fn weight_usage(&self, headers: &HeaderMap) -> Option<f64> {
+        // Binance
+        if let Some(used) = header::<f64>(headers, "x-mbx-used-weight-1m") {
+            let limit = self
+                .rate_limit_key
+                .as_deref()
+                .and_then(rate_limit::capacity)
+                .or_else(|| binance_weight_limit(&self.host))?;
+            return Some(used / limit);
+        }
+        // Upbit: `group=default; min=1800; sec=29`, the limit is not reported
+        if let Some(remaining) = headers.get("remaining-req") {
+            let sec = remaining
+                .to_str()
+                .ok()?
+                .split(';')
+                .find_map(|x| x.trim().strip_prefix("sec="))?
+                .parse::<f64>()
+                .ok()?;
+            return Some(if sec <= 1.0 { 1.0 } else { 0.0 });
+        }
+        // OKX and others, Bybit, Gate.io
+        [
+            ("x-ratelimit-remaining", "x-ratelimit-limit"),
+            ("x-bapi-limit-status", "x-bapi-limit"),
+            ("x-gate-ratelimit-requests-remain", "x-gate-ratelimit-limit"),
+        ]
+        .into_iter()
+        .find_map(|(remaining, limit)| {
+            let remaining = header::<f64>(headers, remaining)?;
+            let limit = header::<f64>(headers, limit)?;
+            (limit > 0.0).then(|| 1.0 - remaining / limit)
+        })
+    }
+}
+
+/// Returns the documented `REQUEST_WEIGHT` limit per minute of Binance APIs.
+fn binance_weight_limit(host: &str) -> Option<f64> {
+    match host {
+        "api.binance.com"
+        | "api1.binance.com"
+        | "api2.binance.com"
+        | "api3.binance.com"
+        | "api4.binance.com"
+        | "api-gcp.binance.com"
+        | "data-api.binance.vision"
+        | "testnet.binance.vision"
+        | "papi.binance.com" => Some(6000.0),
+        "fapi.binance.com" | "dapi.binance.com" | "testnet.binancefuture.com" => Some(2400.0),
+        _ => None,
+    }
+}
+
+fn header<T: FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Polling state of a fetcher, listed by the admin API.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FetcherStatus {
+    /// Current polling period in milliseconds, which may be slowed down by the exchange.
+    period_ms: f64,
+    /// Status code of the last response, or [`None`] if the last request failed.
+    last_status: Option<u16>,
+    /// Milliseconds taken by the last request.
+    last_latency_ms: Option<f64>,
+}
 
 pub struct Fetcher {
     last_data: Arc<Mutex<Option<ResponsePayload>>>,
     notify: Arc<Notify>,
-    kill: Option<oneshot::Sender<()>>,
+    kill: Mutex<Option<oneshot::Sender<()>>>,
+    /// Names of the subscribed strategies, for the strategy label of metrics.
+    subscribers: Arc<Mutex<Vec<String>>>,
+    status: Arc<Mutex<FetcherStatus>>,
 }
 
 impl Fetcher {
@@ -23,69 +165,100 @@ impl Fetcher {
         let last_data = Arc::new(Mutex::new(None));
         let notify = Arc::new(Notify::new());
         let (ktx, krx) = oneshot::channel();
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+        let status = Arc::new(Mutex::new(FetcherStatus::default()));
         tokio::spawn(Self::task(
             payload,
             Arc::clone(&last_data),
             Arc::clone(&notify),
             krx,
             period,
+            Arc::clone(&subscribers),
+            Arc::clone(&status),
         ));
         Self {
             last_data,
             notify,
-            kill: Some(ktx),
+            kill: Mutex::new(Some(ktx)),
+            subscribers,
+            status,
         }
     }
 
+    pub fn status(&self) -> FetcherStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn set_subscribers<'a>(&self, subscribers: impl IntoIterator<Item = &'a String>) {
+        *self.subscribers.lock().unwrap() = subscribers.into_iter().cloned().collect();
+    }
+
     async fn task(
         payload: RequestPayload,
         last_data: Arc<Mutex<Option<ResponsePayload>>>,
         notify: Arc<Notify>,
         mut krx: oneshot::Receiver<()>,
         period: Duration,
+        subscribers: Arc<Mutex<Vec<String>>>,
+        status: Arc<Mutex<FetcherStatus>>,
     ) {
         let mut local_address_index = 0usize;
         let local_addresses = var("GRASSHOPPER_LOCAL_ADDRS")
             .ok()
             .map(|x| x.split(',').map(String::from).collect::<Vec<_>>());
         let clients = if payload.primary_only {
-            vec![reqwest::Client::new()]
+            vec![(reqwest::Client::new(), String::new())]
         } else if let Some(local_addresses) = local_addresses {
             local_addresses
                 .into_iter()
                 .map(|x| {
-                    reqwest::ClientBuilder::new()
+                    let client = reqwest::ClientBuilder::new()
                         .local_address(Some(x.parse().expect("invalid local address")))
                         .build()
-                        .expect("cannot build reqwest client")
+                        .expect("cannot build reqwest client");
+                    (client, x)
                 })
                 .collect()
         } else {
-            vec![reqwest::Client::new()]
+            vec![(reqwest::Client::new(), String::new())]
         };
-        let mut interval = interval(period);
+        let host = Url::parse(&payload.url)
+            .ok()
+            .and_then(|x| x.host_str().map(String::from))
+            .unwrap_or_default();
+        let mut period = AdaptivePeriod::new(period, payload.rate_limit_key().ok(), host);
+        let fetcher_labels = FetcherLabels::new(&payload);
+        let period_gauge = FETCHER_PERIOD_GAUGE.with_label_values(&fetcher_labels.values());
+        period_gauge.set(period.current().as_secs_f64() * 1000.0);
+        status.lock().unwrap().period_ms = period.current().as_secs_f64() * 1000.0;
+        metrics::track_fetcher(&fetcher_labels);
+        // measured from the previous poll, so that polls delayed by rate limits do not burst
+        let mut last_poll: Option<Instant> = None;
         #[cfg(feature = "raydium")]
         let mut raydium_personal_state: Option<
             raydium_amm_v3::states::PersonalPositionState,
         > = None;
         loop {
-            interval.tick().await;
+            let deadline = last_poll.map_or_else(Instant::now, |x| x + period.current());
+            tokio::select! {
+                _ = sleep_until(deadline) => (),
+                _ = &mut krx => break,
+            }
+            last_poll = Some(Instant::now());
             let payload = payload.clone();
-            let req = match payload.clone().into_async_reqwest() {
-                Ok(req) => req,
+            let url = match Url::parse(&payload.url) {
+                Ok(url) => url,
                 Err(err) => {
-                    error!(%err,"cannot convert request");
+                    error!(%err, "cannot parse request URL");
                     debug!(?err);
                     continue;
                 }
             };
-            match req.url().host_str() {
+            match url.host_str() {
                 Some("millis.local") => {
-                    let now = SystemTime::UNIX_EPOCH
-                        .elapsed()
-                        .expect("timestamp before the Unix epoch")
-                        .as_millis();
+                    let now = clock::epoch().as_millis();
                     let payload = ResponsePayload::from_string(&payload.url, now.to_string());
+                    metrics::mark_fetcher_success(&fetcher_labels);
                     *last_data.lock().unwrap() = Some(payload);
                     notify.notify_waiters();
                     continue;
@@ -94,12 +267,11 @@ impl Fetcher {
                 Some("raydium-position.local") => {
                     use solana_sdk::pubkey::Pubkey;
                     local_address_index = local_address_index.overflowing_add(1).0;
-                    let client = &clients[local_address_index % clients.len()];
+                    let (client, _) = &clients[local_address_index % clients.len()];
                     let personal_state = match raydium_personal_state {
                         Some(ref t) => t,
                         None => {
-                            let personal_position_address = req
-                                .url()
+                            let personal_position_address = url
                                 .path()
                                 .trim_start_matches('/')
                                 .parse::<Pubkey>()
@@ -138,23 +310,49 @@ impl Fetcher {
                         &payload.url,
                         format!(r#"["{value_0}","{value_1}"]"#),
                     );
+                    metrics::mark_fetcher_success(&fetcher_labels);
                     *last_data.lock().unwrap() = Some(payload);
                     notify.notify_waiters();
                     continue;
                 }
                 _ => (),
             }
+            if let Some(response) = paper_trading::respond(&payload) {
+                metrics::mark_fetcher_success(&fetcher_labels);
+                *last_data.lock().unwrap() = Some(response);
+                notify.notify_waiters();
+                continue;
+            }
             local_address_index = local_address_index.overflowing_add(1).0;
+            let (client, local_addr) = &clients[local_address_index % clients.len()];
+            let labels = HttpLabels::new(
+                "fetch",
+                &payload.url,
+                &payload.method,
+                local_addr,
+                subscribers.lock().unwrap().clone(),
+            );
+            let started = Instant::now();
             let resp = tokio::select! {
-                r = clients[local_address_index % clients.len()].execute(req) => r,
+                r = payload.execute(client, false, &labels) => r,
                 _ = &mut krx => break,
             };
+            {
+                let mut status = status.lock().unwrap();
+                status.last_status = resp.as_ref().ok().map(|x| x.status().as_u16());
+                status.last_latency_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+            }
 
             match resp {
                 Ok(x) => {
-                    if !x.status().is_success() {
+                    if x.status().is_success() {
+                        metrics::mark_fetcher_success(&fetcher_labels);
+                    } else {
                         error!(%payload.url, %payload.method, status=%x.status(), "request failed");
                     }
+                    period.observe(x.status(), x.headers());
+                    period_gauge.set(period.current().as_secs_f64() * 1000.0);
+                    status.lock().unwrap().period_ms = period.current().as_secs_f64() * 1000.0;
                     let url = x.url().clone();
                     let payload = match ResponsePayload::new(&payload.url, payload.env_suffix, x)
                         .await
@@ -171,6 +369,20 @@ impl Fetcher {
                 Err(err) => error!(%err, "cannot send request"),
             }
         }
+        let _ = FETCHER_PERIOD_GAUGE.remove_label_values(&fetcher_labels.values());
+        metrics::untrack_fetcher(&fetcher_labels);
+    }
+
+    /// Stops the polling task. Payloads already fetched can still be taken with [`Fetcher::next`].
+    pub fn kill(&self) {
+        if let Some(kill) = self.kill.lock().unwrap().take() {
+            let _ = kill.send(());
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_killed(&self) -> bool {
+        self.kill.lock().unwrap().is_none()
     }
 
     /// Returns [`None`] if the sender side of the channel has been dropped.
@@ -186,6 +398,38 @@ impl Fetcher {
 
 impl Drop for Fetcher {
     fn drop(&mut self) {
-        let _ = self.kill.take().unwrap().send(());
+        self.kill();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::AdaptivePeriod;
+
+    fn used_weight(used: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-mbx-used-weight-1m", HeaderValue::from_str(used).unwrap());
+        headers
+    }
+
+    #[test]
+    fn binance_weight_without_configured_bucket() {
+        let period = |host: &str| {
+            AdaptivePeriod::new(
+                Duration::from_secs(1),
+                Some(format!("unconfigured.{host}")),
+                host.to_string(),
+            )
+        };
+        let spot = period("api.binance.com");
+        assert_eq!(spot.weight_usage(&used_weight("3000")), Some(0.5));
+        let futures = period("fapi.binance.com");
+        assert_eq!(futures.weight_usage(&used_weight("2400")), Some(1.0));
+        let unknown = period("example.com");
+        assert_eq!(unknown.weight_usage(&used_weight("2400")), None);
     }
 }
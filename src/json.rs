@@ -0,0 +1,600 @@
+//! JSON documents queried from Lua through opaque handles.
+//!
+//! Unlike `json.decode` in Lua, numbers keep their source text, so they convert to decimals
+//! without loss and large integers such as order IDs stay exact. Nodes of a document are
+//! addressed by their indices, the root being `0`.
+
+use std::{
+    ffi::{c_char, CString},
+    ptr,
+};
+
+use grasshopper_macros::lua_export;
+use rust_decimal::Decimal;
+
+use crate::{
+    lua_decimal::{parse_decimal, DecimalStatus, FfiDecimalResult},
+    LuaStr,
+};
+
+/// Nesting deeper than this is rejected instead of overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+pub struct JsonDocument {
+    nodes: Vec<Node>,
+    error: Option<CString>,
+}
+
+enum Node {
+    Null,
+    Bool(bool),
+    /// Source text of the number.
+    Number(Box<str>),
+    String(Box<str>),
+    Array(Vec<u32>),
+    Object(Vec<(Box<str>, u32)>),
+}
+
+/// Values of `json_type`.
+#[repr(u8)]
+enum NodeType {
+    Null = 0,
+    Bool = 1,
+    Number = 2,
+    String = 3,
+    Array = 4,
+    Object = 5,
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+    nodes: Vec<Node>,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("{message} at byte {}", self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.s.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        if self.s[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            self.error("invalid literal")
+        }
+    }
+
+    /// Parses a value and returns the index of its node.
+    fn value(&mut self, depth: usize) -> Result<u32, String> {
+        if depth > MAX_DEPTH {
+            return self.error("nesting too deep");
+        }
+        self.skip_whitespace();
+        let node = match self.peek() {
+            None => return self.error("unexpected end"),
+            Some(b'n') => self.expect("null").map(|_| Node::Null)?,
+            Some(b't') => self.expect("true").map(|_| Node::Bool(true))?,
+            Some(b'f') => self.expect("false").map(|_| Node::Bool(false))?,
+            Some(b'"') => Node::String(self.string()?.into()),
+            Some(b'-' | b'0'..=b'9') => Node::Number(self.number()?.into()),
+            Some(b'[') => return self.array(depth),
+            Some(b'{') => return self.object(depth),
+            Some(_) => return self.error("unexpected character"),
+        };
+        self.nodes.push(node);
+        Ok(self.nodes.len() as u32 - 1)
+    }
+
+    fn array(&mut self, depth: usize) -> Result<u32, String> {
+        // the node is reserved first, so that parents precede their children
+        let index = self.nodes.len();
+        self.nodes.push(Node::Null);
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+        } else {
+            loop {
+                items.push(self.value(depth + 1)?);
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    Some(b']') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return self.error("expected ',' or ']'"),
+                }
+            }
+        }
+        self.nodes[index] = Node::Array(items);
+        Ok(index as u32)
+    }
+
+    fn object(&mut self, depth: usize) -> Result<u32, String> {
+        let index = self.nodes.len();
+        self.nodes.push(Node::Null);
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+        } else {
+            loop {
+                self.skip_whitespace();
+                if self.peek() != Some(b'"') {
+                    return self.error("expected string key");
+                }
+                let key = self.string()?;
+                self.skip_whitespace();
+                if self.peek() != Some(b':') {
+                    return self.error("expected ':'");
+                }
+                self.pos += 1;
+                members.push((key.into(), self.value(depth + 1)?));
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(b',') => self.pos += 1,
+                    Some(b'}') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return self.error("expected ',' or '}'"),
+                }
+            }
+        }
+        self.nodes[index] = Node::Object(members);
+        Ok(index as u32)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let Some(b) = self.peek() else {
+                return self.error("unterminated string");
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(escaped) = self.peek() else {
+                        return self.error("unterminated string");
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        b'"' | b'\\' | b'/' => out.push(escaped),
+                        b'b' => out.push(b'\x08'),
+                        b'f' => out.push(b'\x0c'),
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'u' => {
+                            let c = self.unicode_escape()?;
+                            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                        }
+                        _ => return self.error("invalid escape"),
+                    }
+                }
+                0..=0x1f => return self.error("control character in string"),
+                _ => out.push(b),
+            }
+        }
+        // the input is valid UTF-8 and escapes are encoded as such
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    /// Parses the digits of a `\u` escape, combining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.s[self.pos..].starts_with(b"\\u") {
+                return self.error("unpaired surrogate");
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return self.error("unpaired surrogate");
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        match char::from_u32(code) {
+            Some(c) => Ok(c),
+            None => self.error("unpaired surrogate"),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .s
+            .get(self.pos..self.pos + 4)
+            .filter(|x| x.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|x| std::str::from_utf8(x).ok())
+            .and_then(|x| u32::from_str_radix(x, 16).ok());
+        match digits {
+            Some(x) => {
+                self.pos += 4;
+                Ok(x)
+            }
+            None => self.error("invalid unicode escape"),
+        }
+    }
+
+    fn number(&mut self) -> Result<&str, String> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return self.error("invalid number"),
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if !self.peek().is_some_and(|x| x.is_ascii_digit()) {
+                return self.error("invalid number");
+            }
+            self.digits();
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if !self.peek().is_some_and(|x| x.is_ascii_digit()) {
+                return self.error("invalid number");
+            }
+            self.digits();
+        }
+        Ok(std::str::from_utf8(&self.s[start..self.pos]).unwrap())
+    }
+
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|x| x.is_ascii_digit()) {
+            self.pos += 1;
+        }
+    }
+}
+
+impl JsonDocument {
+    pub(crate) fn parse(s: &[u8]) -> Result<Self, String> {
+        if let Err(e) = std::str::from_utf8(s) {
+            return Err(format!("invalid UTF-8 at byte {}", e.valid_up_to()));
+        }
+        let mut parser = Parser {
+            s,
+            pos: 0,
+            nodes: Vec::new(),
+        };
+        parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos < s.len() {
+            return parser.error("trailing characters");
+        }
+        Ok(Self {
+            nodes: parser.nodes,
+            error: None,
+        })
+    }
+
+    fn node(&self, index: u32) -> &Node {
+        &self.nodes[index as usize]
+    }
+
+    /// Follows a dotted path of object keys and 1-based array positions from `index`.
+    pub(crate) fn find(&self, mut index: u32, path: &str) -> Option<u32> {
+        if path.is_empty() {
+            return Some(index);
+        }
+        for segment in path.split('.') {
+            index = match self.node(index) {
+                Node::Object(members) => members.iter().rev().find(|(k, _)| **k == *segment)?.1,
+                Node::Array(items) => *items.get(segment.parse::<usize>().ok()?.checked_sub(1)?)?,
+                _ => return None,
+            };
+        }
+        Some(index)
+    }
+
+    /// Returns the item or member value at the 0-based `position`, in document order.
+    pub(crate) fn child(&self, index: u32, position: u32) -> Option<u32> {
+        match self.node(index) {
+            Node::Array(items) => items.get(position as usize).copied(),
+            Node::Object(members) => members.get(position as usize).map(|x| x.1),
+            _ => None,
+        }
+    }
+
+    /// Returns the member key at the 0-based `position` if the node is an object.
+    pub(crate) fn key(&self, index: u32, position: u32) -> Option<&str> {
+        match self.node(index) {
+            Node::Object(members) => members.get(position as usize).map(|x| &*x.0),
+            _ => None,
+        }
+    }
+
+    /// Returns the items if the node is an array.
+    pub(crate) fn items(&self, index: u32) -> Option<&[u32]> {
+        match self.node(index) {
+            Node::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Converts a number, or a string holding one.
+    pub(crate) fn decimal(&self, index: u32) -> Option<Decimal> {
+        match self.node(index) {
+            Node::Number(x) | Node::String(x) => parse_decimal(x),
+            _ => None,
+        }
+    }
+}
+
+/// Parses `s` into a document, which must be freed with [`json_free`]. Check [`json_error`]
+/// before querying it.
+#[lua_export]
+pub extern "C-unwind" fn json_parse(s: LuaStr) -> *mut JsonDocument {
+    let doc = JsonDocument::parse(unsafe { s.as_bytes() }).unwrap_or_else(|e| JsonDocument {
+        nodes: Vec::new(),
+        error: Some(CString::new(e).unwrap()),
+    });
+    Box::into_raw(Box::new(doc))
+}
+
+#[lua_export]
+pub unsafe extern "C-unwind" fn json_free(doc: *mut JsonDocument) {
+    drop(Box::from_raw(doc))
+}
+
+/// Returns the parse error, or null if the document is valid.
+#[lua_export]
+pub unsafe extern "C-unwind" fn json_error(doc: *const JsonDocument) -> *const c_char {
+    match &(*doc).error {
+        Some(e) => e.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Returns the node at `path` from `node`, or -1 if there is none. Path segments are separated
+/// by dots, and array positions count from 1 as in Lua, e.g. `data.1.price`.
+#[lua_export]
+pub unsafe extern "C-unwind" fn json_find(
+    doc: *const JsonDocument,
+    node: u32,
+    path: LuaStr,
+) -> i64 {
+    (*doc).find(node, path.as_str()).map_or(-1, i64::from)
+}
+
+#[lua_export]
+pub unsafe extern "C-unwind" fn json_type(doc: *const JsonDocument, node: u32) -> u8 {
+    let node_type = match (*doc).node(node) {
+        Node::Null => NodeType::Null,
+        Node::Bool(_) => NodeType::Bool,
+        Node::Number(_) => NodeType::Number,
+        Node::String(_) => NodeType::String,
+        Node::Array(_) => NodeType::Array,
+        Node::Object(_) => NodeType::Object,
+    };
+    node_type as u8
+}
+
+/// Returns the number of items or members, or 0 for scalars.
+#[lua_export]
+pub unsafe extern "C-unwind" fn json_len(doc: *const JsonDocument, node: u32) -> u32 {
+    match (*doc).node(node) {
+        Node::Array(items) => items.len() as u32,
+        Node::Object(members) => members.len() as u32,
+        _ => 0,
+    }
+}
+
+/// Returns the item or member value at the 0-based `position`, in document order, or -1 if
+/// there is none.
+#[lua_export]
+pub unsafe extern "C-unwind" fn json_child(
+    doc: *const JsonDocument,
+    node: u32,
+    position: u32,
+) -> i64 {
+    (*doc).child(node, position).map_or(-1, i64::from)
+}
+
+/// Returns the member key at the 0-based `position`, or a null string if the node is not an
+/// object or has no such member. The string lives as long as the document.
+#[lua_export]
+pub unsafe extern "C-unwind" fn json_key(
+    doc: *const JsonDocument,
+    node: u32,
+    position: u32,
+) -> LuaStr {
+    (*doc)
+        .key(node, position)
+        .map_or(LuaStr::null(), |x| LuaStr::from_bytes(x.as_bytes()))
+}
+
+/// Returns the content of a string, or the source text of other scalars. Empty for arrays and
+/// objects. The string lives as long as the document.
+#[lua_export]
+pub unsafe extern "C-unwind" fn json_text(doc: *const JsonDocument, node: u32) -> LuaStr {
+    let text = match (*doc).node(node) {
+        Node::Null => "null",
+        Node::Bool(true) => "true",
+        Node::Bool(false) => "false",
+        Node::Number(x) | Node::String(x) => x,
+        Node::Array(_) | Node::Object(_) => "",
+    };
+    LuaStr::from_bytes(text.as_bytes())
+}
+
+/// Converts a number, or a string holding one, without loss.
+#[lua_export]
+pub unsafe extern "C-unwind" fn json_decimal(
+    doc: *const JsonDocument,
+    node: u32,
+) -> FfiDecimalResult {
+    FfiDecimalResult::from_option((*doc).decimal(node), DecimalStatus::InvalidInput)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::{JsonDocument, Node};
+
+    fn parse(s: &str) -> JsonDocument {
+        JsonDocument::parse(s.as_bytes()).unwrap()
+    }
+
+    fn error(s: &str) -> String {
+        JsonDocument::parse(s.as_bytes()).err().unwrap()
+    }
+
+    fn string(s: &str) -> String {
+        match parse(s).node(0) {
+            Node::String(x) => x.to_string(),
+            _ => panic!("not a string"),
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(string(r#""a\"\\\/\b\f\n\r\tz""#), "a\"\\/\x08\x0c\n\r\tz");
+        assert_eq!(string(r#""\u00e9\u20AC""#), "é€");
+        assert_eq!(string(r#""한글""#), "한글");
+        assert_eq!(error(r#""\x""#), "invalid escape at byte 3");
+        assert_eq!(error(r#""\u12G4""#), "invalid unicode escape at byte 3");
+        assert_eq!(error("\"a\nb\""), "control character in string at byte 3");
+        assert_eq!(error(r#""abc"#), "unterminated string at byte 4");
+    }
+
+    #[test]
+    fn surrogates() {
+        assert_eq!(string(r#""\ud83d\ude00""#), "😀");
+        assert_eq!(string(r#""\uD834\uDD1E""#), "𝄞");
+        assert_eq!(error(r#""\ud83d""#), "unpaired surrogate at byte 7");
+        assert_eq!(error(r#""\ud83dx""#), "unpaired surrogate at byte 7");
+        assert_eq!(error(r#""\ud83d\u0041""#), "unpaired surrogate at byte 13");
+        assert_eq!(error(r#""\ude00""#), "unpaired surrogate at byte 7");
+    }
+
+    #[test]
+    fn numbers() {
+        for x in [
+            "0",
+            "-0",
+            "12",
+            "-12.5",
+            "1e5",
+            "1E+5",
+            "1.5e-3",
+            "123456789012345678901234567890",
+        ] {
+            assert!(
+                matches!(parse(x).node(0), Node::Number(n) if **n == *x),
+                "{x}"
+            );
+        }
+        for x in [
+            "01", "-", "+1", "1.", ".5", "1e", "1e+", "--1", "0x10", "NaN", "Infinity",
+        ] {
+            assert!(JsonDocument::parse(x.as_bytes()).is_err(), "{x}");
+        }
+    }
+
+    #[test]
+    fn trailing_characters() {
+        assert!(JsonDocument::parse(b" {} \n").is_ok());
+        assert_eq!(error("{} x"), "trailing characters at byte 3");
+        assert_eq!(error("[1] [2]"), "trailing characters at byte 4");
+        assert_eq!(error("1 2"), "trailing characters at byte 2");
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert_eq!(error(""), "unexpected end at byte 0");
+        assert_eq!(error("[1,]"), "unexpected character at byte 3");
+        assert_eq!(error("[1 2]"), "expected ',' or ']' at byte 3");
+        assert_eq!(error(r#"{"a" 1}"#), "expected ':' at byte 5");
+        assert_eq!(error("{a:1}"), "expected string key at byte 1");
+        assert_eq!(error(r#"{"a":1,}"#), "expected string key at byte 7");
+        assert_eq!(error("tru"), "invalid literal at byte 0");
+        assert_eq!(error(&"[".repeat(200)), "nesting too deep at byte 129");
+        assert_eq!(
+            JsonDocument::parse(b"\"\xff\"").err().unwrap(),
+            "invalid UTF-8 at byte 1"
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let doc = parse(
+            r#"{"data": [{"price": "1.5"}, {"price": 2, "tags": ["a", "b"]}], "a.b": 1, "x": 1, "x": 2}"#,
+        );
+        let text = |path| match doc.find(0, path).map(|x| doc.node(x)) {
+            Some(Node::Number(x) | Node::String(x)) => Some(x.to_string()),
+            _ => None,
+        };
+        assert_eq!(text("data.1.price").as_deref(), Some("1.5"));
+        assert_eq!(text("data.2.tags.2").as_deref(), Some("b"));
+        // the last duplicate key wins, like in `json.decode`
+        assert_eq!(text("x").as_deref(), Some("2"));
+        assert_eq!(doc.find(0, ""), Some(0));
+        assert_eq!(doc.find(0, "data.0"), None);
+        assert_eq!(doc.find(0, "data.3"), None);
+        assert_eq!(doc.find(0, "data.first"), None);
+        assert_eq!(doc.find(0, "data.1.price.x"), None);
+        assert_eq!(doc.find(0, "a.b"), None);
+        let data = doc.find(0, "data").unwrap();
+        assert_eq!(doc.find(data, "2.price"), doc.find(0, "data.2.price"));
+    }
+
+    #[test]
+    fn decimals() {
+        let doc = parse(
+            r#"[1.50, "2.25", 12345678901234567890, "1,000.5", "1e-3", -0, "abc", true, null, [1]]"#,
+        );
+        let decimal = |position| doc.decimal(doc.child(0, position).unwrap());
+        let d = |x: &str| Some(x.parse::<Decimal>().unwrap());
+        assert_eq!(decimal(0), d("1.50"));
+        assert_eq!(decimal(1), d("2.25"));
+        assert_eq!(decimal(2), d("12345678901234567890"));
+        assert_eq!(decimal(3), d("1000.5"));
+        assert_eq!(decimal(4), d("0.001"));
+        assert_eq!(decimal(5), d("0"));
+        for position in 6..10 {
+            assert_eq!(decimal(position), None);
+        }
+    }
+
+    #[test]
+    fn children_out_of_range() {
+        let doc = parse(r#"{"a": [1, 2], "b": "x"}"#);
+        let a = doc.child(0, 0).unwrap();
+        let b = doc.child(0, 1).unwrap();
+        assert_eq!(doc.key(0, 1), Some("b"));
+        assert_eq!(doc.child(0, 2), None);
+        assert_eq!(doc.key(0, 2), None);
+        assert!(doc.child(a, 1).is_some());
+        assert_eq!(doc.child(a, 2), None);
+        assert_eq!(doc.key(a, 0), None);
+        assert_eq!(doc.child(b, 0), None);
+        assert_eq!(doc.key(b, 0), None);
+    }
+}
@@ -3,38 +3,53 @@ use std::{
     ffi::{c_char, CString},
     fs,
     path::Path,
-    slice,
-    str::FromStr,
+    ptr, slice,
     sync::{Mutex, Once},
     thread::JoinHandle,
     time::Duration,
 };
 
+use admin::LogSink;
 use event::{install, restart};
 use grasshopper_macros::lua_export;
+use log_config::LogConfig;
 use metrics::{metrics_server, ERROR_LOG_COUNTER, WARNING_LOG_COUNTER};
 use notify_debouncer_full::{
     new_debouncer,
     notify::{RecursiveMode, Watcher},
     DebouncedEvent,
 };
+use strategy_log::{is_main_log, is_strategy_log, ScriptSpanLayer, StrategyWriters};
 use tokio::{runtime::Handle, sync::oneshot};
 use tracing::{debug, error};
-use tracing_subscriber::{prelude::*, EnvFilter};
+use tracing_subscriber::{filter, filter::FilterExt, prelude::*, reload, EnvFilter};
 
+mod admin;
 mod borrow_cell;
+mod clock;
+mod dry_run;
 pub mod event;
 mod fetch_aggregator;
 mod fetcher;
+mod json;
+mod log_config;
 pub mod logging;
 pub mod lua_decimal;
 mod math_utils;
 pub mod metrics;
+mod orderbook;
+mod paper_trading;
+mod rate_limit;
 #[cfg(feature = "raydium")]
 mod raydium;
+mod replay;
 mod rethrow;
 mod signer;
+mod strategy_log;
+mod tick_table;
+mod timer;
 mod twilio;
+mod ws_subscriber;
 
 static INITIALIZE_ONCE: Once = Once::new();
 
@@ -49,24 +64,53 @@ pub extern "C-unwind" fn initialize() {
             set_var("RUST_LOG", "info,grasshopper=debug");
         }
 
-        let file_appender = tracing_appender::rolling::daily("logs", "grasshopper.log");
-        let (nb_file, _guard) = tracing_appender::non_blocking(file_appender);
+        let log_config = LogConfig::from_env();
+        let file_writer = log_config
+            .file_writer("grasshopper.log")
+            .expect("cannot open log file");
+        let (nb_file, _guard) = tracing_appender::non_blocking(file_writer);
         std::mem::forget(_guard);
 
+        let (console_filter, console_filter_handle) =
+            reload::Layer::new(EnvFilter::from_default_env());
+        let (file_filter, file_filter_handle) = reload::Layer::new(log_config.file_filter());
+        let (strategy_filter, strategy_filter_handle) =
+            reload::Layer::new(log_config.file_filter());
+        let per_strategy = log_config.per_strategy;
         tracing_subscriber::Registry::default()
+            .with(per_strategy.then_some(ScriptSpanLayer))
             .with(
-                tracing_subscriber::fmt::Layer::default()
-                    .with_level(true)
-                    .with_filter(EnvFilter::from_default_env()),
+                log_config
+                    .console_format
+                    .layer(std::io::stdout, true)
+                    .with_filter(console_filter),
             )
             .with(
-                tracing_subscriber::fmt::Layer::default()
-                    .with_level(true)
-                    .with_ansi(false)
-                    .with_writer(nb_file)
-                    .with_filter(EnvFilter::from_str("info,grasshopper=debug").unwrap()),
+                log_config.file_format.layer(nb_file, false).with_filter(
+                    file_filter.and(
+                        per_strategy.then(|| filter::dynamic_filter_fn(|m, _| is_main_log(m))),
+                    ),
+                ),
             )
+            .with(per_strategy.then(|| {
+                log_config
+                    .file_format
+                    .layer(StrategyWriters::new(log_config.clone()), false)
+                    .with_filter(
+                        strategy_filter.and(filter::dynamic_filter_fn(|m, _| is_strategy_log(m))),
+                    )
+            }))
             .init();
+        admin::set_log_filter_reloader(move |sink, directives| {
+            if sink != Some(LogSink::File) {
+                console_filter_handle.reload(EnvFilter::try_new(directives)?)?;
+            }
+            if sink != Some(LogSink::Console) {
+                file_filter_handle.reload(EnvFilter::try_new(directives)?)?;
+                strategy_filter_handle.reload(EnvFilter::try_new(directives)?)?;
+            }
+            Ok(())
+        });
 
         let prev_hook = std::panic::take_hook();
         std::panic::set_hook(Box::new(move |panic_info| {
@@ -91,6 +135,12 @@ pub extern "C-unwind" fn initialize() {
                 }
             });
 
+            rt.spawn(async {
+                if let Err(err) = admin::admin_server().await {
+                    error!(%err, "cannot start admin server");
+                }
+            });
+
             rt.spawn(async {
                 if let Err(err) = twilio::axum_server().await {
                     error!(%err, "cannot start twilio webhook server");
@@ -212,6 +262,7 @@ pub extern "C" fn reset_metrics(filename: LuaStr) {
         WARNING_LOG_COUNTER.with_label_values(&[filename]).reset();
         ERROR_LOG_COUNTER.with_label_values(&[filename]).reset();
     }
+    metrics::remove_strategy_metrics(filename);
 }
 
 #[repr(C)]
@@ -222,6 +273,24 @@ pub struct LuaStr {
 }
 
 impl LuaStr {
+    pub(crate) const fn null() -> Self {
+        Self {
+            ptr: ptr::null(),
+            len: 0,
+        }
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            ptr: bytes.as_ptr(),
+            len: bytes.len(),
+        }
+    }
+
+    pub(crate) unsafe fn as_bytes<'a>(self) -> &'a [u8] {
+        slice::from_raw_parts(self.ptr, self.len)
+    }
+
     pub(crate) unsafe fn as_str<'a>(self) -> &'a str {
         let s = slice::from_raw_parts(self.ptr, self.len);
         std::str::from_utf8(s)
@@ -0,0 +1,379 @@
+//! Log sinks configured by environment variables:
+//!
+//! - `GRASSHOPPER_LOG_DIR`: directory of log files, `logs` by default
+//! - `GRASSHOPPER_LOG_ROTATION`: `daily`(default), `hourly`, `never`, or a size such as `100MB`
+//!   after which the file is renamed to `<name>.1`, `<name>.2`, ...
+//! - `GRASSHOPPER_LOG_RETENTION`: number of log files to keep including the current one,
+//!   unlimited by default
+//! - `GRASSHOPPER_LOG_FILTER`: filter of the file sink, `info,grasshopper=debug` by default. The
+//!   console sink is filtered by `RUST_LOG`.
+//! - `GRASSHOPPER_LOG_FORMAT`, `GRASSHOPPER_CONSOLE_LOG_FORMAT`: `text`(default) or `json`, of
+//!   the file and the console sink respectively. JSON logs are written one object per line with
+//!   `timestamp`, `level`, `target`, `fields` and `spans` from the root.
+//! - `GRASSHOPPER_LOG_PER_STRATEGY`: if set, strategy logs are written to a file per strategy
+//!   instead of the main file, see [`crate::strategy_log`]
+
+use std::{
+    env::var,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use serde_json::{json, Map, Value as JsonValue};
+use tracing::{
+    field::{Field, Visit},
+    span::Record,
+    Event, Subscriber,
+};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{
+    field::RecordFields,
+    fmt::{
+        format::Writer,
+        time::{FormatTime, SystemTime},
+        FmtContext, FormatEvent, FormatFields, FormattedFields, MakeWriter,
+    },
+    registry::LookupSpan,
+    EnvFilter, Layer,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LogRotation {
+    Daily,
+    Hourly,
+    Never,
+    /// Rotates once the file grows over the given number of bytes.
+    Size(u64),
+}
+
+impl LogRotation {
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().to_ascii_lowercase();
+        match s.as_str() {
+            "daily" => return Some(Self::Daily),
+            "hourly" => return Some(Self::Hourly),
+            "never" => return Some(Self::Never),
+            _ => (),
+        }
+        let (digits, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+        let unit = match unit.trim() {
+            "" | "b" => 1,
+            "kb" => 1 << 10,
+            "mb" => 1 << 20,
+            "gb" => 1 << 30,
+            _ => return None,
+        };
+        let size = digits.parse::<u64>().ok()?.checked_mul(unit)?;
+        (size > 0).then_some(Self::Size(size))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    fn from_env(name: &str) -> Self {
+        match var(name).as_deref().map(str::trim) {
+            Err(_) | Ok("text") => Self::Text,
+            Ok("json") => Self::Json,
+            Ok(x) => panic!("invalid {name}: {x}"),
+        }
+    }
+
+    /// Returns a `fmt` layer of this format writing to `writer`.
+    pub(crate) fn layer<S, W>(self, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+    {
+        let layer = tracing_subscriber::fmt::Layer::default()
+            .with_level(true)
+            .with_ansi(ansi)
+            .with_writer(writer);
+        match self {
+            Self::Text => Box::new(layer),
+            Self::Json => Box::new(layer.event_format(JsonFormat).fmt_fields(JsonFields)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct LogConfig {
+    dir: PathBuf,
+    rotation: LogRotation,
+    retention: Option<usize>,
+    file_filter: String,
+    pub(crate) file_format: LogFormat,
+    pub(crate) console_format: LogFormat,
+    pub(crate) per_strategy: bool,
+}
+
+impl LogConfig {
+    pub(crate) fn from_env() -> Self {
+        let rotation = var("GRASSHOPPER_LOG_ROTATION").map_or(LogRotation::Daily, |x| {
+            LogRotation::parse(&x)
+                .unwrap_or_else(|| panic!("invalid GRASSHOPPER_LOG_ROTATION: {x}"))
+        });
+        let retention = var("GRASSHOPPER_LOG_RETENTION").ok().map(|x| {
+            x.trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&x| x > 0)
+                .unwrap_or_else(|| panic!("invalid GRASSHOPPER_LOG_RETENTION: {x}"))
+        });
+        Self {
+            dir: var("GRASSHOPPER_LOG_DIR").map_or_else(|_| PathBuf::from("logs"), PathBuf::from),
+            rotation,
+            retention,
+            file_filter: var("GRASSHOPPER_LOG_FILTER")
+                .unwrap_or_else(|_| "info,grasshopper=debug".to_string()),
+            file_format: LogFormat::from_env("GRASSHOPPER_LOG_FORMAT"),
+            console_format: LogFormat::from_env("GRASSHOPPER_CONSOLE_LOG_FORMAT"),
+            per_strategy: var("GRASSHOPPER_LOG_PER_STRATEGY").is_ok(),
+        }
+    }
+
+    pub(crate) fn file_filter(&self) -> EnvFilter {
+        EnvFilter::try_new(&self.file_filter).expect("invalid GRASSHOPPER_LOG_FILTER")
+    }
+
+    /// Opens the log file `file_name` in the log directory, rotated as configured.
+    pub(crate) fn file_writer(&self, file_name: &str) -> io::Result<Box<dyn Write + Send>> {
+        let rotation = match self.rotation {
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Never => Rotation::NEVER,
+            LogRotation::Size(max_size) => {
+                fs::create_dir_all(&self.dir)?;
+                return Ok(Box::new(SizeRollingFile::open(
+                    self.dir.join(file_name),
+                    max_size,
+                    self.retention,
+                )?));
+            }
+        };
+        let mut builder = RollingFileAppender::builder()
+            .rotation(rotation)
+            .filename_prefix(file_name);
+        if let Some(retention) = self.retention {
+            builder = builder.max_log_files(retention);
+        }
+        builder
+            .build(&self.dir)
+            .map(|x| Box::new(x) as Box<dyn Write + Send>)
+            .map_err(io::Error::other)
+    }
+}
+
+/// Log file which is renamed to `<path>.1`, shifting older ones to `<path>.2` and so on, once it
+/// grows over `max_size` bytes.
+struct SizeRollingFile {
+    path: PathBuf,
+    max_size: u64,
+    /// Number of files to keep including the current one, unlimited if [`None`].
+    retention: Option<usize>,
+    file: File,
+    size: u64,
+}
+
+impl SizeRollingFile {
+    fn open(path: PathBuf, max_size: u64, retention: Option<usize>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_size,
+            retention,
+            file,
+            size,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let mut last = 0;
+        while self.rotated_path(last + 1).exists() {
+            last += 1;
+        }
+        let keep = self.retention.map_or(usize::MAX, |x| x - 1);
+        for index in (1..=last).rev() {
+            if index >= keep {
+                fs::remove_file(self.rotated_path(index))?;
+            } else {
+                fs::rename(self.rotated_path(index), self.rotated_path(index + 1))?;
+            }
+        }
+        if keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[derive(Default)]
+struct JsonVisitor(Map<String, JsonValue>);
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{value:?}")));
+    }
+}
+
+/// Formats span fields as JSON objects, to be read back by [`JsonFormat`].
+struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        fields.record(&mut visitor);
+        write!(writer, "{}", JsonValue::Object(visitor.0))
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &Record<'_>,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor(serde_json::from_str(&current.fields).unwrap_or_default());
+        fields.record(&mut visitor);
+        current.fields = JsonValue::Object(visitor.0).to_string();
+        Ok(())
+    }
+}
+
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        let mut fields = JsonVisitor::default();
+        event.record(&mut fields);
+        let spans = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|x| x.from_root())
+            .map(|span| {
+                let mut object = span
+                    .extensions()
+                    .get::<FormattedFields<N>>()
+                    .and_then(|x| serde_json::from_str::<Map<_, _>>(&x.fields).ok())
+                    .unwrap_or_default();
+                object.insert("name".to_string(), json!(span.name()));
+                JsonValue::Object(object)
+            })
+            .collect::<Vec<_>>();
+        let metadata = event.metadata();
+        let line = json!({
+            "timestamp": timestamp,
+            "level": metadata.level().to_string(),
+            "target": metadata.target(),
+            "fields": fields.0,
+            "spans": spans,
+        });
+        writeln!(writer, "{line}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation() {
+        assert_eq!(LogRotation::parse("Daily"), Some(LogRotation::Daily));
+        assert_eq!(LogRotation::parse("never"), Some(LogRotation::Never));
+        assert_eq!(LogRotation::parse("4096"), Some(LogRotation::Size(4096)));
+        assert_eq!(
+            LogRotation::parse("100MB"),
+            Some(LogRotation::Size(100 << 20))
+        );
+        assert_eq!(LogRotation::parse("1 gb"), Some(LogRotation::Size(1 << 30)));
+        assert_eq!(LogRotation::parse("0MB"), None);
+        assert_eq!(LogRotation::parse("weekly"), None);
+        assert_eq!(LogRotation::parse("10TB"), None);
+    }
+
+    #[test]
+    fn size_rolling_file() {
+        let dir = std::env::temp_dir().join(format!("grasshopper-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.log");
+        let mut file = SizeRollingFile::open(path.clone(), 10, Some(3)).unwrap();
+        for line in ["aaaaaa\n", "bbbbbb\n", "cccccc\n", "dddddd\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        let read = |x: &str| fs::read_to_string(dir.join(x)).unwrap();
+        assert_eq!(read("test.log"), "dddddd\n");
+        assert_eq!(read("test.log.1"), "cccccc\n");
+        assert_eq!(read("test.log.2"), "bbbbbb\n");
+        assert!(!dir.join("test.log.3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,23 @@
-use std::{cell::Cell, panic::catch_unwind};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    panic::catch_unwind,
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+};
 
-use tracing::{debug, error, info, info_span, trace, warn, Instrument};
+use once_cell::sync::Lazy;
+use serde_json::Value as JsonValue;
+use tracing::{
+    callsite::{self, Callsite, Identifier},
+    debug, dispatcher, error,
+    field::{display, DisplayValue, FieldSet, Value},
+    info, info_span,
+    level_filters::LevelFilter,
+    metadata::Kind,
+    subscriber::Interest,
+    trace, warn, Event, Instrument, Level, Metadata,
+};
 
 use crate::{
     metrics::{ERROR_LOG_COUNTER, WARNING_LOG_COUNTER},
@@ -23,6 +40,16 @@ pub extern "C-unwind" fn set_script_name(script_name: LuaStr) {
     }
 }
 
+/// Returns the script name set by [`set_script_name`], if any.
+pub(crate) fn current_script_name() -> Option<String> {
+    SCRIPT_NAME.with(|x| {
+        let script_name = x.take();
+        let ret = script_name.as_deref().map(String::from);
+        x.set(script_name);
+        ret
+    })
+}
+
 #[no_mangle]
 pub extern "C-unwind" fn trace(message: LuaStr) {
     SCRIPT_NAME.with(|x| {
@@ -89,6 +116,193 @@ pub extern "C-unwind" fn error(message: LuaStr) {
     })
 }
 
+/// Maximum number of fields of a [`log`] event. Further fields are dropped.
+const MAX_LOG_FIELDS: usize = 32;
+
+/// Maximum number of [`LuaCallsite`]s. Events with other sets of field names are recorded with
+/// all their fields as JSON in a single [`OVERFLOW_FIELD`] instead.
+const MAX_LUA_CALLSITES: usize = 1024;
+
+const OVERFLOW_FIELD: &str = "fields";
+
+/// Callsite of [`log`] events with a particular level and set of field names. Callsites are
+/// leaked, as `tracing` requires them to be `'static`, but reused for the same field names.
+struct LuaCallsite {
+    metadata: OnceLock<Metadata<'static>>,
+}
+
+impl Callsite for LuaCallsite {
+    fn set_interest(&self, _interest: Interest) {}
+
+    fn metadata(&self) -> &Metadata<'_> {
+        self.metadata.get().expect("callsite without metadata")
+    }
+}
+
+/// Level and field names of a [`LuaCallsite`].
+type CallsiteKey = (Level, Vec<String>);
+
+static LUA_CALLSITES: Lazy<Mutex<HashMap<CallsiteKey, &'static LuaCallsite>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Returns the metadata of a `message` field followed by `field_names`, or [`None`] if there
+/// are too many callsites already.
+fn lua_callsite_metadata(level: Level, field_names: &[&str]) -> Option<&'static Metadata<'static>> {
+    let key = (level, field_names.iter().map(|x| x.to_string()).collect());
+    let mut callsites = LUA_CALLSITES.lock().unwrap();
+    if callsites.len() >= MAX_LUA_CALLSITES
+        && field_names != [OVERFLOW_FIELD]
+        && !callsites.contains_key(&key)
+    {
+        return None;
+    }
+    let callsite = *callsites.entry(key).or_insert_with(|| {
+        let callsite: &'static LuaCallsite = Box::leak(Box::new(LuaCallsite {
+            metadata: OnceLock::new(),
+        }));
+        let names = std::iter::once("message")
+            .chain(
+                field_names
+                    .iter()
+                    .map(|x| &*Box::leak(x.to_string().into_boxed_str())),
+            )
+            .collect::<Vec<_>>();
+        let metadata = Metadata::new(
+            "lua log",
+            module_path!(),
+            level,
+            None,
+            None,
+            Some(module_path!()),
+            FieldSet::new(Box::leak(names.into_boxed_slice()), Identifier(callsite)),
+            Kind::EVENT,
+        );
+        let _ = callsite.metadata.set(metadata);
+        callsite::register(callsite);
+        callsite
+    });
+    callsite.metadata.get()
+}
+
+enum FieldValue<'a> {
+    Str(&'a str),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+    /// Arrays and objects, recorded as JSON text.
+    Json(DisplayValue<&'a JsonValue>),
+}
+
+impl<'a> FieldValue<'a> {
+    /// Returns [`None`] for nulls, which are not recorded.
+    fn new(value: &'a JsonValue) -> Option<Self> {
+        Some(match value {
+            JsonValue::Null => return None,
+            JsonValue::Bool(x) => Self::Bool(*x),
+            JsonValue::Number(x) => match (x.as_i64(), x.as_u64()) {
+                (Some(x), _) => Self::I64(x),
+                (None, Some(x)) => Self::U64(x),
+                _ => Self::F64(x.as_f64().unwrap_or(f64::NAN)),
+            },
+            JsonValue::String(x) => Self::Str(x),
+            JsonValue::Array(_) | JsonValue::Object(_) => Self::Json(display(value)),
+        })
+    }
+
+    fn as_value(&self) -> &dyn Value {
+        match self {
+            Self::Str(x) => x,
+            Self::I64(x) => x,
+            Self::U64(x) => x,
+            Self::F64(x) => x,
+            Self::Bool(x) => x,
+            Self::Json(x) => x,
+        }
+    }
+}
+
+/// Emits an event whose fields are the members of the JSON object `fields`, besides the message.
+fn log_with_fields(level: Level, message: &str, fields: &str) {
+    if level > LevelFilter::current() {
+        return;
+    }
+    let fields = if fields.is_empty() {
+        serde_json::Map::new()
+    } else {
+        match serde_json::from_str::<JsonValue>(fields) {
+            Ok(JsonValue::Object(x)) => x,
+            // `json.encode({})` gives an empty array
+            Ok(JsonValue::Array(x)) if x.is_empty() => serde_json::Map::new(),
+            _ => {
+                error!(fields, "log fields are not a JSON object");
+                serde_json::Map::new()
+            }
+        }
+    };
+    let all_fields = JsonValue::Object(fields);
+    let mut fields = all_fields
+        .as_object()
+        .unwrap()
+        .iter()
+        .filter(|(name, _)| name.as_str() != "message")
+        .filter_map(|(name, value)| Some((name.as_str(), FieldValue::new(value)?)))
+        .take(MAX_LOG_FIELDS)
+        .collect::<Vec<_>>();
+    let names = fields.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    let metadata = match lua_callsite_metadata(level, &names) {
+        Some(x) => x,
+        None => {
+            fields = vec![(OVERFLOW_FIELD, FieldValue::Json(display(&all_fields)))];
+            lua_callsite_metadata(level, &[OVERFLOW_FIELD]).unwrap()
+        }
+    };
+    dispatcher::get_default(|dispatch| {
+        if !dispatch.enabled(metadata) {
+            return;
+        }
+        let field_set = metadata.fields();
+        let message_field = field_set.field("message").unwrap();
+        let message = display(message);
+        let field_handles = fields
+            .iter()
+            .map(|(name, _)| field_set.field(name).unwrap())
+            .collect::<Vec<_>>();
+        // `ValueSet`s take arrays, so unused slots are filled with an unrecorded message
+        let values: [_; MAX_LOG_FIELDS + 1] = std::array::from_fn(|i| match i {
+            0 => (&message_field, Some(&message as &dyn Value)),
+            i if i <= fields.len() => (&field_handles[i - 1], Some(fields[i - 1].1.as_value())),
+            _ => (&message_field, None),
+        });
+        dispatch.event(&Event::new(metadata, &field_set.value_set(&values)));
+    });
+}
+
+/// Logs `message` at `level`(`trace`, `debug`, `info`, `warn` or `error`) with the members of
+/// the JSON object `fields` as event fields, e.g. `{"price": "123.4", "side": "buy"}`.
+#[no_mangle]
+pub extern "C-unwind" fn log(level: LuaStr, message: LuaStr, fields: LuaStr) {
+    SCRIPT_NAME.with(|x| {
+        let script_name = x.take();
+        let level = Level::from_str(unsafe { level.as_str() }).unwrap_or(Level::INFO);
+        let _ = catch_unwind(|| {
+            info_span!("logging", script_name).in_scope(|| {
+                log_with_fields(level, unsafe { message.as_str() }, unsafe {
+                    fields.as_str()
+                })
+            });
+        });
+        if let Some(name) = &script_name {
+            if level == Level::WARN {
+                WARNING_LOG_COUNTER.with_label_values(&[name]).inc();
+            } else if level == Level::ERROR {
+                ERROR_LOG_COUNTER.with_label_values(&[name]).inc();
+            }
+        }
+        x.set(script_name);
+    })
+}
+
 #[no_mangle]
 pub extern "C-unwind" fn notice(message: LuaStr) {
     let message = unsafe { message.as_str().to_string() };
@@ -120,3 +334,26 @@ pub extern "C-unwind" fn emergency(message: LuaStr) {
         .instrument(info_span!("emergency_task")),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+
+    use super::{lua_callsite_metadata, LUA_CALLSITES, MAX_LUA_CALLSITES, OVERFLOW_FIELD};
+
+    #[test]
+    fn callsites_are_capped() {
+        let first = lua_callsite_metadata(Level::INFO, &["price", "side"]).unwrap();
+        for i in 0..MAX_LUA_CALLSITES {
+            lua_callsite_metadata(Level::INFO, &[&format!("key{i}")]);
+        }
+        assert_eq!(LUA_CALLSITES.lock().unwrap().len(), MAX_LUA_CALLSITES);
+        assert!(lua_callsite_metadata(Level::INFO, &["new"]).is_none());
+        // existing callsites are still reused
+        let reused = lua_callsite_metadata(Level::INFO, &["price", "side"]).unwrap();
+        assert!(std::ptr::eq(first, reused));
+        let overflow = lua_callsite_metadata(Level::WARN, &[OVERFLOW_FIELD]).unwrap();
+        assert!(overflow.fields().field("message").is_some());
+        assert!(overflow.fields().field(OVERFLOW_FIELD).is_some());
+    }
+}
@@ -1,12 +1,11 @@
 use std::{
     borrow::{Borrow, Cow},
     ffi::{c_char, CString},
-    time::Instant,
 };
 
 use crate::{math_utils, LuaStr};
 use grasshopper_macros::lua_export;
-use rust_decimal::{Decimal, MathematicalOps};
+use rust_decimal::{Decimal, MathematicalOps, RoundingStrategy};
 use tracing::error;
 
 #[repr(C)]
@@ -30,22 +29,186 @@ impl From<FfiDecimal> for Decimal {
     }
 }
 
-#[lua_export]
-pub extern "C-unwind" fn decimal_from_string(s: LuaStr) -> FfiDecimal {
-    let mut s = Cow::<str>::Borrowed(unsafe { s.as_str() });
+/// Outcome of a checked operation. The value is zero unless the status is [`Ok`].
+///
+/// [`Ok`]: DecimalStatus::Ok
+#[repr(C)]
+pub struct FfiDecimalResult {
+    status: DecimalStatus,
+    value: FfiDecimal,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecimalStatus {
+    Ok = 0,
+    Overflow = 1,
+    DivisionByZero = 2,
+    /// Unparsable strings, or operands outside of the domain(e.g. fractional powers of negative
+    /// numbers).
+    InvalidInput = 3,
+}
+
+impl FfiDecimalResult {
+    fn ok(value: Decimal) -> Self {
+        Self {
+            status: DecimalStatus::Ok,
+            value: value.into(),
+        }
+    }
+
+    fn err(status: DecimalStatus) -> Self {
+        Self {
+            status,
+            value: Decimal::ZERO.into(),
+        }
+    }
+
+    pub(crate) fn from_option(value: Option<Decimal>, status: DecimalStatus) -> Self {
+        value.map_or_else(|| Self::err(status), Self::ok)
+    }
+}
+
+pub(crate) fn parse_decimal(s: &str) -> Option<Decimal> {
+    let mut s = Cow::<str>::Borrowed(s);
     if s.contains(',') {
         s = Cow::Owned(s.replace(',', ""));
     }
     s.parse::<Decimal>()
         .or_else(|_| Decimal::from_scientific(s.borrow()))
-        .map_err(|e| {
-            error!(str = s.into_owned(), "cannot parse decimal string");
-            e
+        .ok()
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_checked_from_string(s: LuaStr) -> FfiDecimalResult {
+    let value = std::str::from_utf8(unsafe { s.as_bytes() })
+        .ok()
+        .and_then(parse_decimal);
+    FfiDecimalResult::from_option(value, DecimalStatus::InvalidInput)
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_checked_add(x: FfiDecimal, y: FfiDecimal) -> FfiDecimalResult {
+    let x = Decimal::from(x);
+    let y = Decimal::from(y);
+    FfiDecimalResult::from_option(x.checked_add(y), DecimalStatus::Overflow)
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_checked_sub(x: FfiDecimal, y: FfiDecimal) -> FfiDecimalResult {
+    let x = Decimal::from(x);
+    let y = Decimal::from(y);
+    FfiDecimalResult::from_option(x.checked_sub(y), DecimalStatus::Overflow)
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_checked_mul(x: FfiDecimal, y: FfiDecimal) -> FfiDecimalResult {
+    let x = Decimal::from(x);
+    let y = Decimal::from(y);
+    FfiDecimalResult::from_option(x.checked_mul(y), DecimalStatus::Overflow)
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_checked_div(x: FfiDecimal, y: FfiDecimal) -> FfiDecimalResult {
+    let x = Decimal::from(x);
+    let y = Decimal::from(y);
+    if y.is_zero() {
+        return FfiDecimalResult::err(DecimalStatus::DivisionByZero);
+    }
+    FfiDecimalResult::from_option(x.checked_div(y), DecimalStatus::Overflow)
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_checked_mod(x: FfiDecimal, y: FfiDecimal) -> FfiDecimalResult {
+    let x = Decimal::from(x);
+    let y = Decimal::from(y);
+    if y.is_zero() {
+        return FfiDecimalResult::err(DecimalStatus::DivisionByZero);
+    }
+    FfiDecimalResult::from_option(x.checked_rem(y), DecimalStatus::Overflow)
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_checked_pow(x: FfiDecimal, y: FfiDecimal) -> FfiDecimalResult {
+    let x = Decimal::from(x);
+    let y = Decimal::from(y);
+    if x.is_zero() && y.is_sign_negative() && !y.is_zero() {
+        return FfiDecimalResult::err(DecimalStatus::DivisionByZero);
+    }
+    if x.is_sign_negative() && !x.is_zero() && y.fract() != Decimal::ZERO {
+        return FfiDecimalResult::err(DecimalStatus::InvalidInput);
+    }
+    FfiDecimalResult::from_option(x.checked_powd(y), DecimalStatus::Overflow)
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_from_string(s: LuaStr) -> FfiDecimal {
+    let s = unsafe { s.as_str() };
+    parse_decimal(s)
+        .unwrap_or_else(|| {
+            error!(str = s, "cannot parse decimal string");
+            panic!("cannot parse string into Decimal")
         })
-        .expect("cannot parse string into Decimal")
         .into()
 }
 
+/// Converts `x` to a decimal of its 14 significant digits, as `tostring` prints it, so
+/// `0.1 + 0.2` is `0.3`. Digits beyond 28 decimal places are rounded.
+#[lua_export]
+pub extern "C-unwind" fn decimal_from_f64(x: f64) -> FfiDecimalResult {
+    if !x.is_finite() {
+        return FfiDecimalResult::err(DecimalStatus::InvalidInput);
+    }
+    FfiDecimalResult::from_option(round_f64(x), DecimalStatus::Overflow)
+}
+
+/// Rounds `x` to 14 significant digits like `%.14g`, without trailing zeros.
+fn round_f64(x: f64) -> Option<Decimal> {
+    // e.g. `-1.2345678901234e-5`
+    let s = format!("{x:.13e}");
+    let (digits, exponent) = s.split_once('e')?;
+    let mut mantissa: i64 = digits.replace('.', "").parse().ok()?;
+    let mut scale = 13 - exponent.parse::<i64>().ok()?;
+    if mantissa == 0 {
+        return Some(Decimal::ZERO);
+    }
+    while mantissa % 10 == 0 {
+        mantissa /= 10;
+        scale -= 1;
+    }
+    if scale < 0 {
+        Decimal::from(mantissa).checked_mul(Decimal::TEN.checked_powi(-scale)?)
+    } else if scale <= 28 {
+        Some(Decimal::new(mantissa, scale as u32))
+    } else {
+        // rounded by the division, or zero if even the divisor overflows
+        Some(
+            Decimal::TEN
+                .checked_powi(scale - 28)
+                .map_or(Decimal::ZERO, |x| Decimal::new(mantissa, 28) / x),
+        )
+    }
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_from_i64(x: i64) -> FfiDecimal {
+    Decimal::from(x).into()
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_from_u64(x: u64) -> FfiDecimal {
+    Decimal::from(x).into()
+}
+
+/// Returns `mantissa * 10^-scale`. Scales above 28 are invalid.
+#[lua_export]
+pub extern "C-unwind" fn decimal_from_parts(mantissa: i64, scale: u32) -> FfiDecimalResult {
+    FfiDecimalResult::from_option(
+        Decimal::try_new(mantissa, scale).ok(),
+        DecimalStatus::InvalidInput,
+    )
+}
+
 #[lua_export]
 pub extern "C-unwind" fn decimal_to_string(x: FfiDecimal) -> *mut c_char {
     CString::new(Decimal::from(x).to_string())
@@ -157,10 +320,316 @@ pub extern "C-unwind" fn decimal_min(x: FfiDecimal, y: FfiDecimal) -> FfiDecimal
 }
 
 #[lua_export]
-extern "C-unwind" fn millis() -> FfiDecimal {
-    thread_local! {
-        static NOW: Instant = Instant::now();
+pub extern "C-unwind" fn decimal_sqrt(x: FfiDecimal) -> FfiDecimalResult {
+    FfiDecimalResult::from_option(Decimal::from(x).sqrt(), DecimalStatus::InvalidInput)
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_ln(x: FfiDecimal) -> FfiDecimalResult {
+    FfiDecimalResult::from_option(Decimal::from(x).checked_ln(), DecimalStatus::InvalidInput)
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_exp(x: FfiDecimal) -> FfiDecimalResult {
+    FfiDecimalResult::from_option(Decimal::from(x).checked_exp(), DecimalStatus::Overflow)
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_trunc(x: FfiDecimal) -> FfiDecimal {
+    Decimal::from(x).trunc().into()
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_signum(x: FfiDecimal) -> FfiDecimal {
+    let x = Decimal::from(x);
+    if x.is_zero() {
+        Decimal::ZERO.into()
+    } else if x.is_sign_negative() {
+        Decimal::NEGATIVE_ONE.into()
+    } else {
+        Decimal::ONE.into()
+    }
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_is_zero(x: FfiDecimal) -> bool {
+    Decimal::from(x).is_zero()
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_scale(x: FfiDecimal) -> u32 {
+    Decimal::from(x).scale()
+}
+
+/// Changes the scale to `scale`(at most 28), rounding midpoints away from zero.
+#[lua_export]
+pub extern "C-unwind" fn decimal_rescale(x: FfiDecimal, scale: u32) -> FfiDecimal {
+    let mut x = Decimal::from(x);
+    x.rescale(scale);
+    x.into()
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_normalize(x: FfiDecimal) -> FfiDecimal {
+    Decimal::from(x).normalize().into()
+}
+
+/// Converts to the nearest `f64`, which may lose precision.
+#[lua_export]
+pub extern "C-unwind" fn decimal_to_number(x: FfiDecimal) -> f64 {
+    f64::try_from(Decimal::from(x)).expect("cannot convert Decimal to f64")
+}
+
+/// Maps rounding modes of `decimal.lua` to [`RoundingStrategy`].
+fn rounding_strategy(mode: u32) -> Option<RoundingStrategy> {
+    match mode {
+        0 => Some(RoundingStrategy::MidpointNearestEven),
+        1 => Some(RoundingStrategy::MidpointAwayFromZero),
+        2 => Some(RoundingStrategy::MidpointTowardZero),
+        3 => Some(RoundingStrategy::ToZero),
+        4 => Some(RoundingStrategy::AwayFromZero),
+        5 => Some(RoundingStrategy::ToNegativeInfinity),
+        6 => Some(RoundingStrategy::ToPositiveInfinity),
+        _ => None,
+    }
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_floor_to_step(
+    x: FfiDecimal,
+    step: FfiDecimal,
+) -> FfiDecimalResult {
+    let step = Decimal::from(step);
+    if step.is_zero() {
+        return FfiDecimalResult::err(DecimalStatus::DivisionByZero);
+    }
+    FfiDecimalResult::from_option(
+        math_utils::floor_to_step(Decimal::from(x), step),
+        DecimalStatus::Overflow,
+    )
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_ceil_to_step(x: FfiDecimal, step: FfiDecimal) -> FfiDecimalResult {
+    let step = Decimal::from(step);
+    if step.is_zero() {
+        return FfiDecimalResult::err(DecimalStatus::DivisionByZero);
+    }
+    FfiDecimalResult::from_option(
+        math_utils::ceil_to_step(Decimal::from(x), step),
+        DecimalStatus::Overflow,
+    )
+}
+
+#[lua_export]
+pub extern "C-unwind" fn decimal_round_to_step(
+    x: FfiDecimal,
+    step: FfiDecimal,
+    mode: u32,
+) -> FfiDecimalResult {
+    let step = Decimal::from(step);
+    let Some(mode) = rounding_strategy(mode) else {
+        return FfiDecimalResult::err(DecimalStatus::InvalidInput);
+    };
+    if step.is_zero() {
+        return FfiDecimalResult::err(DecimalStatus::DivisionByZero);
+    }
+    FfiDecimalResult::from_option(
+        math_utils::round_to_step(Decimal::from(x), step, mode),
+        DecimalStatus::Overflow,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::{
+        decimal_checked_add, decimal_checked_div, decimal_checked_from_string, decimal_checked_mod,
+        decimal_checked_mul, decimal_checked_pow, decimal_checked_sub, decimal_exp,
+        decimal_from_f64, decimal_from_i64, decimal_from_parts, decimal_from_u64, decimal_ln,
+        decimal_normalize, decimal_rescale, decimal_scale, decimal_signum, decimal_sqrt,
+        decimal_trunc, DecimalStatus, FfiDecimal, FfiDecimalResult,
+    };
+    use crate::LuaStr;
+
+    fn d(x: &str) -> Decimal {
+        x.parse().unwrap()
+    }
+
+    /// Returns the value of an [`Ok`](DecimalStatus::Ok) result, or its status.
+    fn value(result: FfiDecimalResult) -> Result<Decimal, DecimalStatus> {
+        match result.status {
+            DecimalStatus::Ok => Ok(result.value.into()),
+            status => Err(status),
+        }
+    }
+
+    #[test]
+    fn from_f64_keeps_tostring_digits() {
+        for (x, expected) in [
+            (0.1 + 0.2, "0.3"),
+            (0.1, "0.1"),
+            (100.0, "100"),
+            (-1.5, "-1.5"),
+            (1.0 / 3.0, "0.33333333333333"),
+            (123456789.12345678, "123456789.12346"),
+            (1e-5, "0.00001"),
+            (1e20, "100000000000000000000"),
+            (2f64.powi(53), "9007199254741000"),
+            (-0.0, "0"),
+        ] {
+            let decimal = value(decimal_from_f64(x)).unwrap();
+            assert_eq!(decimal.to_string(), expected, "{x}");
+        }
+        assert_eq!(value(decimal_from_f64(1e-30)), Ok(Decimal::ZERO));
+        assert_eq!(
+            value(decimal_from_f64(1.5e-27)),
+            Ok(d("0.0000000000000000000000000015"))
+        );
+        assert_eq!(value(decimal_from_f64(1e30)), Err(DecimalStatus::Overflow));
+        for x in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(value(decimal_from_f64(x)), Err(DecimalStatus::InvalidInput));
+        }
+    }
+
+    #[test]
+    fn from_integers_and_parts() {
+        assert_eq!(
+            Decimal::from(decimal_from_i64(i64::MIN)),
+            Decimal::from(i64::MIN)
+        );
+        assert_eq!(
+            Decimal::from(decimal_from_u64(u64::MAX)),
+            Decimal::from(u64::MAX)
+        );
+        assert_eq!(value(decimal_from_parts(12345, 2)), Ok(d("123.45")));
+        assert_eq!(
+            value(decimal_from_parts(-1, 28)),
+            Ok(d("-0.0000000000000000000000000001"))
+        );
+        assert_eq!(
+            value(decimal_from_parts(1, 29)),
+            Err(DecimalStatus::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn checked_operations() {
+        type Op = extern "C-unwind" fn(FfiDecimal, FfiDecimal) -> FfiDecimalResult;
+        let cases: [(Op, Decimal, Decimal, Result<Decimal, DecimalStatus>); 13] = [
+            (decimal_checked_add, d("1.5"), d("2"), Ok(d("3.5"))),
+            (
+                decimal_checked_add,
+                Decimal::MAX,
+                d("1"),
+                Err(DecimalStatus::Overflow),
+            ),
+            (
+                decimal_checked_sub,
+                Decimal::MIN,
+                d("1"),
+                Err(DecimalStatus::Overflow),
+            ),
+            (
+                decimal_checked_mul,
+                Decimal::MAX,
+                d("2"),
+                Err(DecimalStatus::Overflow),
+            ),
+            (decimal_checked_div, d("1"), d("4"), Ok(d("0.25"))),
+            (
+                decimal_checked_div,
+                d("1"),
+                d("0"),
+                Err(DecimalStatus::DivisionByZero),
+            ),
+            (
+                decimal_checked_div,
+                Decimal::MAX,
+                d("0.1"),
+                Err(DecimalStatus::Overflow),
+            ),
+            (
+                decimal_checked_mod,
+                d("7"),
+                d("0"),
+                Err(DecimalStatus::DivisionByZero),
+            ),
+            (decimal_checked_mod, d("-7"), d("3"), Ok(d("-1"))),
+            (decimal_checked_pow, d("2"), d("10"), Ok(d("1024"))),
+            (
+                decimal_checked_pow,
+                d("0"),
+                d("-1"),
+                Err(DecimalStatus::DivisionByZero),
+            ),
+            (
+                decimal_checked_pow,
+                d("-2"),
+                d("0.5"),
+                Err(DecimalStatus::InvalidInput),
+            ),
+            (
+                decimal_checked_pow,
+                d("10"),
+                d("100"),
+                Err(DecimalStatus::Overflow),
+            ),
+        ];
+        for (op, x, y, expected) in cases {
+            assert_eq!(value(op(x.into(), y.into())), expected, "{x}, {y}");
+        }
+    }
+
+    #[test]
+    fn checked_from_string() {
+        let parse = |x: &[u8]| value(decimal_checked_from_string(LuaStr::from_bytes(x)));
+        assert_eq!(parse(b"1,234.5"), Ok(d("1234.5")));
+        assert_eq!(parse(b"1e-3"), Ok(d("0.001")));
+        for x in [&b""[..], b"abc", b"1.2.3", b"\xff"] {
+            assert_eq!(parse(x), Err(DecimalStatus::InvalidInput), "{x:?}");
+        }
+    }
+
+    #[test]
+    fn domain_errors() {
+        assert_eq!(value(decimal_sqrt(d("6.25").into())), Ok(d("2.5")));
+        assert_eq!(
+            value(decimal_sqrt(d("-1").into())),
+            Err(DecimalStatus::InvalidInput)
+        );
+        assert_eq!(value(decimal_ln(d("1").into())), Ok(Decimal::ZERO));
+        for x in ["0", "-1"] {
+            assert_eq!(
+                value(decimal_ln(d(x).into())),
+                Err(DecimalStatus::InvalidInput),
+                "{x}"
+            );
+        }
+        assert_eq!(value(decimal_exp(Decimal::ZERO.into())), Ok(Decimal::ONE));
+        assert_eq!(
+            value(decimal_exp(d("1000").into())),
+            Err(DecimalStatus::Overflow)
+        );
+    }
+
+    #[test]
+    fn scale_helpers() {
+        let unary = |f: extern "C-unwind" fn(FfiDecimal) -> FfiDecimal, x: &str| {
+            Decimal::from(f(d(x).into())).to_string()
+        };
+        assert_eq!(unary(decimal_trunc, "-1.75"), "-1");
+        assert_eq!(unary(decimal_signum, "-0.01"), "-1");
+        assert_eq!(unary(decimal_signum, "0.00"), "0");
+        assert_eq!(unary(decimal_normalize, "1.500"), "1.5");
+        assert_eq!(decimal_scale(d("1.500").into()), 3);
+        let rescale =
+            |x: &str, scale| Decimal::from(decimal_rescale(d(x).into(), scale)).to_string();
+        assert_eq!(rescale("1.245", 2), "1.25");
+        assert_eq!(rescale("-1.245", 2), "-1.25");
+        assert_eq!(rescale("1.5", 3), "1.500");
+        // scales above 28 are clamped
+        assert_eq!(decimal_scale(decimal_rescale(d("1").into(), 40)), 28);
     }
-    (Decimal::try_from(NOW.with(|x| x.elapsed().as_nanos())).unwrap() / Decimal::new(1_000_000, 0))
-        .into()
 }
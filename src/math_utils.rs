@@ -1,4 +1,4 @@
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, RoundingStrategy};
 
 /// Returns the `floor`-ed value using decimals_to_unit(decimals) and nearest to zero strategy.
 ///
@@ -120,3 +120,72 @@ pub fn round_to_decimals(mut value: Decimal, decimals: i32) -> Decimal {
         value.round() / multiplier
     }
 }
+
+/// Returns the value rounded to a multiple of `step` using nearest to zero strategy, or [`None`]
+/// if `step` is zero or the result overflows.
+///
+/// # Examples
+///
+/// ```
+/// # use rust_decimal_macros::dec;
+/// # use grasshopper::math_utils::floor_to_step;
+/// assert_eq!(floor_to_step(dec!(1.74), dec!(0.5)), Some(dec!(1.5)));
+/// assert_eq!(floor_to_step(dec!(-1.74), dec!(0.5)), Some(dec!(-1.5)));
+/// assert_eq!(floor_to_step(dec!(49), dec!(25)), Some(dec!(25)));
+/// assert_eq!(floor_to_step(dec!(0.0124), dec!(0.0025)), Some(dec!(0.0100)));
+/// assert_eq!(floor_to_step(dec!(10), dec!(5)), Some(dec!(10)));
+/// assert_eq!(floor_to_step(dec!(10), dec!(0)), None);
+/// ```
+pub fn floor_to_step(value: Decimal, step: Decimal) -> Option<Decimal> {
+    round_to_step(value, step, RoundingStrategy::ToZero)
+}
+
+/// Returns the value rounded to a multiple of `step` using farthest to zero strategy, or [`None`]
+/// if `step` is zero or the result overflows.
+///
+/// # Examples
+///
+/// ```
+/// # use rust_decimal::Decimal;
+/// # use rust_decimal_macros::dec;
+/// # use grasshopper::math_utils::ceil_to_step;
+/// assert_eq!(ceil_to_step(dec!(1.26), dec!(0.5)), Some(dec!(1.5)));
+/// assert_eq!(ceil_to_step(dec!(-1.26), dec!(0.5)), Some(dec!(-1.5)));
+/// assert_eq!(ceil_to_step(dec!(26), dec!(25)), Some(dec!(50)));
+/// assert_eq!(ceil_to_step(dec!(0.0101), dec!(0.0025)), Some(dec!(0.0125)));
+/// assert_eq!(ceil_to_step(dec!(10), dec!(5)), Some(dec!(10)));
+/// assert_eq!(ceil_to_step(Decimal::MAX, dec!(10)), None);
+/// ```
+pub fn ceil_to_step(value: Decimal, step: Decimal) -> Option<Decimal> {
+    round_to_step(value, step, RoundingStrategy::AwayFromZero)
+}
+
+/// Returns the value rounded to a multiple of `step` using `mode`, or [`None`] if `step` is zero
+/// or the result overflows.
+///
+/// # Examples
+///
+/// ```
+/// # use rust_decimal::{Decimal, RoundingStrategy};
+/// # use rust_decimal_macros::dec;
+/// # use grasshopper::math_utils::round_to_step;
+/// let even = RoundingStrategy::MidpointNearestEven;
+/// assert_eq!(round_to_step(dec!(1.24), dec!(0.5), even), Some(dec!(1.0)));
+/// assert_eq!(round_to_step(dec!(1.25), dec!(0.5), even), Some(dec!(1.0)));
+/// assert_eq!(round_to_step(dec!(1.75), dec!(0.5), even), Some(dec!(2.0)));
+/// assert_eq!(round_to_step(dec!(-1.75), dec!(0.5), even), Some(dec!(-2.0)));
+/// assert_eq!(round_to_step(dec!(37.5), dec!(25), even), Some(dec!(50)));
+/// let away = RoundingStrategy::MidpointAwayFromZero;
+/// assert_eq!(round_to_step(dec!(1.25), dec!(0.5), away), Some(dec!(1.5)));
+/// assert_eq!(round_to_step(dec!(12.5), dec!(5), away), Some(dec!(15)));
+/// let floor = RoundingStrategy::ToNegativeInfinity;
+/// assert_eq!(round_to_step(dec!(-0.0101), dec!(0.0025), floor), Some(dec!(-0.0125)));
+/// assert_eq!(round_to_step(dec!(1.26), dec!(0), floor), None);
+/// assert_eq!(round_to_step(Decimal::MAX, dec!(0.1), floor), None);
+/// ```
+pub fn round_to_step(value: Decimal, step: Decimal, mode: RoundingStrategy) -> Option<Decimal> {
+    value
+        .checked_div(step)?
+        .round_dp_with_strategy(0, mode)
+        .checked_mul(step)
+}
@@ -1,13 +1,25 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    env::var,
+    hash::{Hash, Hasher},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
 use axum::{routing::get, Router};
+use eyre::Context;
 use grasshopper_macros::lua_export;
 use once_cell::sync::Lazy;
 use prometheus::{
-    default_registry, exponential_buckets, register_histogram_vec, register_int_counter_vec,
-    HistogramVec, IntCounterVec, TextEncoder,
+    default_registry, exponential_buckets, register_gauge_vec, register_histogram_vec,
+    register_int_counter_vec, CounterVec, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec,
+    Opts, TextEncoder, DEFAULT_BUCKETS,
 };
+use reqwest::{Method, StatusCode, Url};
 use rust_decimal::Decimal;
+use serde_json::Value;
 
-use crate::{lua_decimal::FfiDecimal, LuaStr};
+use crate::{event::RequestPayload, logging::current_script_name, lua_decimal::FfiDecimal, LuaStr};
 
 pub(crate) static WARNING_LOG_COUNTER: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!(
@@ -47,10 +59,407 @@ pub(crate) static WALL_ELAPSED_HISTOGRAM: Lazy<HistogramVec> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Labels of HTTP request metrics: source(`fetch` or `send`), host, path template, method, local
+/// address and strategy.
+const HTTP_LABELS: &[&str] = &["source", "host", "path", "method", "local_addr", "strategy"];
+
+pub(crate) static HTTP_DURATION_HISTOGRAM: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "grasshopper_http_duration",
+        "Milliseconds of each HTTP request attempt of fetchers and sends",
+        HTTP_LABELS,
+        exponential_buckets(1.0, 1.25, 50).unwrap(),
+    )
+    .unwrap()
+});
+
+pub(crate) static HTTP_RESPONSE_COUNTER: Lazy<IntCounterVec> = Lazy::new(|| {
+    let labels = [HTTP_LABELS, &["status"]].concat();
+    register_int_counter_vec!(
+        "grasshopper_http_responses",
+        "Number of HTTP responses of fetchers and sends by status code",
+        &labels
+    )
+    .unwrap()
+});
+
+pub(crate) static HTTP_ERROR_COUNTER: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "grasshopper_http_errors",
+        "Number of HTTP request attempts of fetchers and sends failed without a response",
+        HTTP_LABELS
+    )
+    .unwrap()
+});
+
+/// Labels of subscription metrics. Subscriptions to the same URL are told apart by method,
+/// environment suffix and a hash of the body(empty without a body).
+const FETCHER_LABELS: &[&str] = &["url", "method", "env_suffix", "body"];
+
+pub(crate) static FETCHER_PERIOD_GAUGE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "grasshopper_fetcher_period",
+        "Milliseconds of current polling period of each subscription",
+        FETCHER_LABELS
+    )
+    .unwrap()
+});
+
+pub(crate) static FETCHER_STALENESS_GAUGE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "grasshopper_fetcher_staleness",
+        "Seconds since the last successful response of each subscription",
+        FETCHER_LABELS
+    )
+    .unwrap()
+});
+
+/// Time of the last successful response of each subscription, keyed by its label values, from
+/// which [`FETCHER_STALENESS_GAUGE`] is computed on scrapes.
+static FETCHER_LAST_SUCCESS: Lazy<Mutex<HashMap<[String; 4], Instant>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Label values of the metrics of a subscription.
+pub(crate) struct FetcherLabels {
+    values: [String; 4],
+}
+
+impl FetcherLabels {
+    pub(crate) fn new(payload: &RequestPayload) -> Self {
+        let body = payload
+            .body
+            .as_ref()
+            .map(|x| {
+                let mut hasher = DefaultHasher::new();
+                x.hash(&mut hasher);
+                format!("{:016x}", hasher.finish())
+            })
+            .unwrap_or_default();
+        Self {
+            values: [
+                payload.url.clone(),
+                payload.method.to_string(),
+                payload.env_suffix.clone().unwrap_or_default(),
+                body,
+            ],
+        }
+    }
+
+    pub(crate) fn values(&self) -> [&str; 4] {
+        self.values.each_ref().map(String::as_str)
+    }
+}
+
+/// Label values of the HTTP request metrics of a request. A request shared by several strategies
+/// is recorded in the series of each of them.
+pub(crate) struct HttpLabels {
+    values: [String; 5],
+    strategies: Vec<String>,
+}
+
+impl HttpLabels {
+    pub(crate) fn new(
+        source: &str,
+        url: &str,
+        method: &Method,
+        local_addr: &str,
+        strategies: Vec<String>,
+    ) -> Self {
+        let (host, path) = match Url::parse(url) {
+            Ok(x) => (
+                x.host_str().unwrap_or_default().to_string(),
+                path_template(x.path()),
+            ),
+            Err(_) => Default::default(),
+        };
+        Self {
+            values: [
+                source.to_string(),
+                host,
+                path,
+                method.to_string(),
+                local_addr.to_string(),
+            ],
+            strategies,
+        }
+    }
+
+    /// Records an attempt which took `elapsed`, and returned `status` or failed without a
+    /// response.
+    pub(crate) fn observe(&self, elapsed: Duration, status: Option<StatusCode>) {
+        for strategy in &self.strategies {
+            let values = [&self.values.each_ref().map(String::as_str)[..], &[strategy]].concat();
+            HTTP_DURATION_HISTOGRAM
+                .with_label_values(&values)
+                .observe(elapsed.as_secs_f64() * 1000.0);
+            match status {
+                Some(status) => {
+                    let status = status.as_u16().to_string();
+                    let values = [&values[..], &[&status]].concat();
+                    HTTP_RESPONSE_COUNTER.with_label_values(&values).inc();
+                }
+                None => HTTP_ERROR_COUNTER.with_label_values(&values).inc(),
+            }
+        }
+    }
+}
+
+/// Replaces the path segments which look like IDs, i.e. numbers, UUIDs and long hex strings,
+/// with `{}` so that the label values stay bounded.
+fn path_template(path: &str) -> String {
+    path.split('/')
+        .map(|x| if is_id(x) { "{}" } else { x })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_id(segment: &str) -> bool {
+    let is_hex = |x: &str| !x.is_empty() && x.bytes().all(|b| b.is_ascii_hexdigit());
+    let is_uuid = || {
+        let groups = segment.split('-').collect::<Vec<_>>();
+        groups.iter().map(|x| x.len()).eq([8, 4, 4, 4, 12]) && groups.iter().all(|x| is_hex(x))
+    };
+    (!segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()))
+        || (segment.len() >= 16 && is_hex(segment))
+        || is_uuid()
+}
+
+/// Starts tracking the staleness of the subscription, counting from now.
+pub(crate) fn track_fetcher(labels: &FetcherLabels) {
+    FETCHER_LAST_SUCCESS
+        .lock()
+        .unwrap()
+        .insert(labels.values.clone(), Instant::now());
+}
+
+pub(crate) fn mark_fetcher_success(labels: &FetcherLabels) {
+    if let Some(x) = FETCHER_LAST_SUCCESS.lock().unwrap().get_mut(&labels.values) {
+        *x = Instant::now();
+    }
+}
+
+pub(crate) fn untrack_fetcher(labels: &FetcherLabels) {
+    FETCHER_LAST_SUCCESS.lock().unwrap().remove(&labels.values);
+    let _ = FETCHER_STALENESS_GAUGE.remove_label_values(&labels.values());
+}
+
+fn update_fetcher_staleness() {
+    for (values, last_success) in FETCHER_LAST_SUCCESS.lock().unwrap().iter() {
+        FETCHER_STALENESS_GAUGE
+            .with_label_values(&values.each_ref().map(String::as_str))
+            .set(last_success.elapsed().as_secs_f64());
+    }
+}
+
+/// Metrics registered by strategies, keyed by name.
+static CUSTOM_METRICS: Lazy<Mutex<HashMap<String, CustomMetric>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn custom_metrics() -> MutexGuard<'static, HashMap<String, CustomMetric>> {
+    CUSTOM_METRICS.lock().unwrap()
+}
+
+/// Label of custom metrics holding the name of the strategy which updated them.
+const STRATEGY_LABEL: &str = "strategy";
+
+/// Outcome of registering or updating a custom metric.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricStatus {
+    Ok = 0,
+    /// The label names are not a JSON array of strings.
+    InvalidLabelNames = 1,
+    /// The label names include [`STRATEGY_LABEL`].
+    ReservedLabel = 2,
+    /// The histogram buckets are not a JSON array of numbers.
+    InvalidBuckets = 3,
+    /// Prometheus rejected the metric, e.g. for an invalid name or label name.
+    InvalidMetric = 4,
+    /// A metric of another kind or labels is registered with the same name.
+    Conflict = 5,
+    NotRegistered = 6,
+    /// The metric is not of the kind of the update, e.g. `set_gauge` on a counter.
+    WrongKind = 7,
+    /// The label values are not a JSON object.
+    InvalidLabels = 8,
+    /// A label of the metric has no value.
+    MissingLabel = 9,
+    /// A label value is given for a label the metric does not have.
+    UnknownLabel = 10,
+    NegativeIncrement = 11,
+}
+
+impl MetricStatus {
+    fn of(result: Result<(), Self>) -> Self {
+        result.err().unwrap_or(Self::Ok)
+    }
+}
+
+struct CustomMetric {
+    vec: CustomMetricVec,
+    /// Label names, [`STRATEGY_LABEL`] first.
+    label_names: Vec<String>,
+    /// Label values of the updated series, to remove them when a strategy is reloaded.
+    series: HashSet<Vec<String>>,
+}
+
+#[derive(Clone)]
+enum CustomMetricVec {
+    Gauge(GaugeVec),
+    Counter(CounterVec),
+    Histogram(HistogramVec),
+}
+
+impl CustomMetricVec {
+    fn kind(&self) -> &'static str {
+        match self {
+            CustomMetricVec::Gauge(_) => "gauge",
+            CustomMetricVec::Counter(_) => "counter",
+            CustomMetricVec::Histogram(_) => "histogram",
+        }
+    }
+
+    fn gauge(&self) -> Result<&GaugeVec, MetricStatus> {
+        match self {
+            CustomMetricVec::Gauge(x) => Ok(x),
+            _ => Err(MetricStatus::WrongKind),
+        }
+    }
+
+    fn counter(&self) -> Result<&CounterVec, MetricStatus> {
+        match self {
+            CustomMetricVec::Counter(x) => Ok(x),
+            _ => Err(MetricStatus::WrongKind),
+        }
+    }
+
+    fn histogram(&self) -> Result<&HistogramVec, MetricStatus> {
+        match self {
+            CustomMetricVec::Histogram(x) => Ok(x),
+            _ => Err(MetricStatus::WrongKind),
+        }
+    }
+
+    fn remove_label_values(&self, values: &[&str]) {
+        let _ = match self {
+            CustomMetricVec::Gauge(x) => x.remove_label_values(values),
+            CustomMetricVec::Counter(x) => x.remove_label_values(values),
+            CustomMetricVec::Histogram(x) => x.remove_label_values(values),
+        };
+    }
+}
+
+/// Parses a JSON array of label names, prepending [`STRATEGY_LABEL`].
+fn parse_label_names(label_names: LuaStr) -> Result<Vec<String>, MetricStatus> {
+    let label_names = unsafe { label_names.as_str() };
+    let label_names: Vec<String> =
+        serde_json::from_str(label_names).map_err(|_| MetricStatus::InvalidLabelNames)?;
+    if label_names.iter().any(|x| x == STRATEGY_LABEL) {
+        return Err(MetricStatus::ReservedLabel);
+    }
+    Ok([STRATEGY_LABEL.to_string()]
+        .into_iter()
+        .chain(label_names)
+        .collect())
+}
+
+/// Registers a metric built by `new` from the label names, unless one with the same kind and
+/// labels is already registered(e.g. by an earlier run of the strategy).
+fn register_custom_metric(
+    name: LuaStr,
+    label_names: LuaStr,
+    new: impl FnOnce(&[&str]) -> prometheus::Result<CustomMetricVec>,
+) -> Result<(), MetricStatus> {
+    let name = unsafe { name.as_str() };
+    let label_names = parse_label_names(label_names)?;
+    let label_refs = label_names.iter().map(String::as_str).collect::<Vec<_>>();
+    let vec = new(&label_refs).map_err(|_| MetricStatus::InvalidMetric)?;
+
+    let mut metrics = custom_metrics();
+    if let Some(existing) = metrics.get(name) {
+        if existing.vec.kind() != vec.kind() || existing.label_names != label_names {
+            return Err(MetricStatus::Conflict);
+        }
+        return Ok(());
+    }
+    let registered = match vec.clone() {
+        CustomMetricVec::Gauge(x) => default_registry().register(Box::new(x)),
+        CustomMetricVec::Counter(x) => default_registry().register(Box::new(x)),
+        CustomMetricVec::Histogram(x) => default_registry().register(Box::new(x)),
+    };
+    // e.g. a metric of the runtime with the same name
+    registered.map_err(|_| MetricStatus::Conflict)?;
+    metrics.insert(
+        name.to_string(),
+        CustomMetric {
+            vec,
+            label_names,
+            series: HashSet::new(),
+        },
+    );
+    Ok(())
+}
+
+/// Calls `f` with the metric `name` and its label values, which are the current strategy name
+/// followed by the values in `labels`, a JSON object keyed by label names.
+fn with_custom_metric(
+    name: LuaStr,
+    labels: LuaStr,
+    f: impl FnOnce(&CustomMetricVec, &[&str]) -> Result<(), MetricStatus>,
+) -> Result<(), MetricStatus> {
+    let name = unsafe { name.as_str() };
+    let labels = unsafe { labels.as_str() };
+    let mut label_values: HashMap<String, Value> = match serde_json::from_str(labels) {
+        Ok(Value::Object(x)) => x.into_iter().collect(),
+        // empty Lua tables are encoded as arrays
+        Ok(Value::Array(x)) if x.is_empty() => HashMap::new(),
+        _ => return Err(MetricStatus::InvalidLabels),
+    };
+
+    let mut metrics = custom_metrics();
+    let metric = metrics.get_mut(name).ok_or(MetricStatus::NotRegistered)?;
+    let mut values = vec![current_script_name().unwrap_or_default()];
+    for label_name in &metric.label_names[1..] {
+        let value = match label_values.remove(label_name) {
+            Some(Value::String(x)) => x,
+            Some(x) => x.to_string(),
+            None => return Err(MetricStatus::MissingLabel),
+        };
+        values.push(value);
+    }
+    if !label_values.is_empty() {
+        return Err(MetricStatus::UnknownLabel);
+    }
+    let value_refs = values.iter().map(String::as_str).collect::<Vec<_>>();
+    f(&metric.vec, &value_refs)?;
+    metric.series.insert(values);
+    Ok(())
+}
+
+fn metric_value(value: FfiDecimal) -> f64 {
+    f64::try_from(Decimal::from(value)).expect("cannot convert Decimal to f64")
+}
+
+/// Removes the series of custom metrics updated by `strategy`.
+pub(crate) fn remove_strategy_metrics(strategy: &str) {
+    for metric in custom_metrics().values_mut() {
+        metric.series.retain(|values| {
+            if values[0] != strategy {
+                return true;
+            }
+            let value_refs = values.iter().map(String::as_str).collect::<Vec<_>>();
+            metric.vec.remove_label_values(&value_refs);
+            false
+        });
+    }
+}
+
+/// Serves `/metrics` on `GRASSHOPPER_METRICS_ADDR`, or `0.0.0.0:8000` if not set.
 pub(crate) async fn metrics_server() -> eyre::Result<()> {
     let router = Router::new().route(
         "/metrics",
         get(|| async {
+            update_fetcher_staleness();
             let encoder = TextEncoder::new();
             let mut buffer = String::with_capacity(4 << 10);
             encoder
@@ -60,7 +469,11 @@ pub(crate) async fn metrics_server() -> eyre::Result<()> {
         }),
     );
 
-    axum::Server::bind(&"0.0.0.0:8000".parse().unwrap())
+    let addr = var("GRASSHOPPER_METRICS_ADDR").unwrap_or_else(|_| "0.0.0.0:8000".to_string());
+    let addr = addr
+        .parse()
+        .with_context(|| format!("invalid metrics server address {addr}"))?;
+    axum::Server::bind(&addr)
         .serve(router.into_make_service())
         .await?;
     Ok(())
@@ -82,3 +495,304 @@ pub extern "C-unwind" fn report_timings(
         .with_label_values(&[strategy_name])
         .observe(f64::try_from(wall_elapsed).expect("cannot convert Decimal to f64"));
 }
+
+/// Registers a gauge labelled by the strategy name and `label_names`, a JSON array.
+#[lua_export]
+pub extern "C-unwind" fn register_gauge(
+    name: LuaStr,
+    help: LuaStr,
+    label_names: LuaStr,
+) -> MetricStatus {
+    let opts = Opts::new(unsafe { name.as_str() }, unsafe { help.as_str() });
+    MetricStatus::of(register_custom_metric(name, label_names, |labels| {
+        GaugeVec::new(opts, labels).map(CustomMetricVec::Gauge)
+    }))
+}
+
+/// Registers a counter labelled by the strategy name and `label_names`, a JSON array.
+#[lua_export]
+pub extern "C-unwind" fn register_counter(
+    name: LuaStr,
+    help: LuaStr,
+    label_names: LuaStr,
+) -> MetricStatus {
+    let opts = Opts::new(unsafe { name.as_str() }, unsafe { help.as_str() });
+    MetricStatus::of(register_custom_metric(name, label_names, |labels| {
+        CounterVec::new(opts, labels).map(CustomMetricVec::Counter)
+    }))
+}
+
+/// Registers a histogram labelled by the strategy name and `label_names`, a JSON array.
+/// `buckets` is a JSON array of upper bounds, or empty for the default buckets.
+#[lua_export]
+pub extern "C-unwind" fn register_histogram(
+    name: LuaStr,
+    help: LuaStr,
+    label_names: LuaStr,
+    buckets: LuaStr,
+) -> MetricStatus {
+    let buckets = unsafe { buckets.as_str() };
+    let buckets = if buckets.is_empty() {
+        DEFAULT_BUCKETS.to_vec()
+    } else {
+        match serde_json::from_str::<Vec<f64>>(buckets) {
+            Ok(x) => x,
+            Err(_) => return MetricStatus::InvalidBuckets,
+        }
+    };
+    let opts =
+        HistogramOpts::new(unsafe { name.as_str() }, unsafe { help.as_str() }).buckets(buckets);
+    MetricStatus::of(register_custom_metric(name, label_names, |labels| {
+        HistogramVec::new(opts, labels).map(CustomMetricVec::Histogram)
+    }))
+}
+
+#[lua_export]
+pub extern "C-unwind" fn set_gauge(
+    name: LuaStr,
+    labels: LuaStr,
+    value: FfiDecimal,
+) -> MetricStatus {
+    MetricStatus::of(with_custom_metric(name, labels, |metric, values| {
+        metric
+            .gauge()?
+            .with_label_values(values)
+            .set(metric_value(value));
+        Ok(())
+    }))
+}
+
+#[lua_export]
+pub extern "C-unwind" fn add_gauge(
+    name: LuaStr,
+    labels: LuaStr,
+    value: FfiDecimal,
+) -> MetricStatus {
+    MetricStatus::of(with_custom_metric(name, labels, |metric, values| {
+        metric
+            .gauge()?
+            .with_label_values(values)
+            .add(metric_value(value));
+        Ok(())
+    }))
+}
+
+/// Increases a counter by `value`, which must not be negative.
+#[lua_export]
+pub extern "C-unwind" fn inc_counter(
+    name: LuaStr,
+    labels: LuaStr,
+    value: FfiDecimal,
+) -> MetricStatus {
+    let value = metric_value(value);
+    if value < 0.0 {
+        return MetricStatus::NegativeIncrement;
+    }
+    MetricStatus::of(with_custom_metric(name, labels, |metric, values| {
+        metric.counter()?.with_label_values(values).inc_by(value);
+        Ok(())
+    }))
+}
+
+#[lua_export]
+pub extern "C-unwind" fn observe_histogram(
+    name: LuaStr,
+    labels: LuaStr,
+    value: FfiDecimal,
+) -> MetricStatus {
+    MetricStatus::of(with_custom_metric(name, labels, |metric, values| {
+        metric
+            .histogram()?
+            .with_label_values(values)
+            .observe(metric_value(value));
+        Ok(())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use prometheus::{core::Collector, proto::Metric};
+    use reqwest::{Method, StatusCode};
+    use rust_decimal::Decimal;
+
+    use super::{
+        inc_counter, path_template, register_counter, register_gauge, register_histogram,
+        set_gauge, FetcherLabels, HttpLabels, MetricStatus, FETCHER_LAST_SUCCESS,
+        FETCHER_PERIOD_GAUGE, HTTP_ERROR_COUNTER, HTTP_RESPONSE_COUNTER,
+    };
+    use crate::{event::RequestPayload, fetcher::Fetcher, LuaStr};
+
+    fn s(x: &str) -> LuaStr {
+        LuaStr::from_bytes(x.as_bytes())
+    }
+
+    #[test]
+    fn custom_metric_statuses() {
+        assert_eq!(
+            register_gauge(s("test_status_gauge"), s("help"), s(r#"["side"]"#)),
+            MetricStatus::Ok
+        );
+        // registering again with the same labels is allowed
+        assert_eq!(
+            register_gauge(s("test_status_gauge"), s("help"), s(r#"["side"]"#)),
+            MetricStatus::Ok
+        );
+        for (label_names, status) in [
+            ("side", MetricStatus::InvalidLabelNames),
+            (r#"["strategy"]"#, MetricStatus::ReservedLabel),
+            (r#"["bad-label"]"#, MetricStatus::InvalidMetric),
+            (r#"["market"]"#, MetricStatus::Conflict),
+        ] {
+            assert_eq!(
+                register_gauge(s("test_status_gauge"), s("help"), s(label_names)),
+                status
+            );
+        }
+        assert_eq!(
+            register_counter(s("test_status_gauge"), s("help"), s(r#"["side"]"#)),
+            MetricStatus::Conflict
+        );
+        assert_eq!(
+            register_histogram(s("test_status_histogram"), s("help"), s("[]"), s("[1,")),
+            MetricStatus::InvalidBuckets
+        );
+
+        for (name, labels, status) in [
+            ("test_status_gauge", r#"{"side":"buy"}"#, MetricStatus::Ok),
+            (
+                "test_status_unknown",
+                r#"{"side":"buy"}"#,
+                MetricStatus::NotRegistered,
+            ),
+            ("test_status_gauge", r#""buy""#, MetricStatus::InvalidLabels),
+            ("test_status_gauge", "[]", MetricStatus::MissingLabel),
+            (
+                "test_status_gauge",
+                r#"{"side":"buy","market":"spot"}"#,
+                MetricStatus::UnknownLabel,
+            ),
+        ] {
+            assert_eq!(
+                set_gauge(s(name), s(labels), Decimal::ONE.into()),
+                status,
+                "{name} {labels}"
+            );
+        }
+        assert_eq!(
+            inc_counter(
+                s("test_status_gauge"),
+                s(r#"{"side":"buy"}"#),
+                Decimal::ONE.into()
+            ),
+            MetricStatus::WrongKind
+        );
+        assert_eq!(
+            register_counter(s("test_status_counter"), s("help"), s("[]")),
+            MetricStatus::Ok
+        );
+        assert_eq!(
+            inc_counter(s("test_status_counter"), s("[]"), (-Decimal::ONE).into()),
+            MetricStatus::NegativeIncrement
+        );
+    }
+
+    #[test]
+    fn path_templates() {
+        for path in [
+            "/api/v3/depth",
+            "/api/v3/allOrders",
+            "/api/v3/ticker/24hr",
+            "/fapi/v2/balance",
+            "/fapi/v2/positionRisk",
+            "/v5/market/orderbook",
+            "/api/v4/spot/currency_pairs/BTC_USDT",
+            "/",
+        ] {
+            assert_eq!(path_template(path), path);
+        }
+        assert_eq!(path_template("/api/v1/orders/123456"), "/api/v1/orders/{}");
+        assert_eq!(
+            path_template("/v1/order/6d3c8f6e-4c1a-4b2e-9f3a-0123456789ab/cancel"),
+            "/v1/order/{}/cancel"
+        );
+        assert_eq!(
+            path_template("/api/v1/tx/5c504ed432cb51138bcf09aa5e8a410d"),
+            "/api/v1/tx/{}"
+        );
+    }
+
+    #[test]
+    fn shared_requests_are_recorded_per_strategy() {
+        let url = "http://api.example.com/api/v3/depth";
+        let strategies = vec!["a".to_string(), "b".to_string()];
+        let labels = HttpLabels::new("fetch", url, &Method::GET, "", strategies);
+        labels.observe(Duration::from_millis(5), Some(StatusCode::OK));
+        labels.observe(Duration::from_millis(5), None);
+        for strategy in ["a", "b"] {
+            let values = [
+                "fetch",
+                "api.example.com",
+                "/api/v3/depth",
+                "GET",
+                "",
+                strategy,
+            ];
+            let response_values = [&values[..], &["200"]].concat();
+            let responses = HTTP_RESPONSE_COUNTER.with_label_values(&response_values);
+            assert_eq!(responses.get(), 1);
+            assert_eq!(HTTP_ERROR_COUNTER.with_label_values(&values).get(), 1);
+        }
+    }
+
+    fn payload(method: &str) -> RequestPayload {
+        let url = "http://millis.local/staleness";
+        serde_json::from_str(&format!(r#"{{"url":"{url}","method":"{method}"}}"#)).unwrap()
+    }
+
+    fn is_tracked(labels: &FetcherLabels) -> bool {
+        FETCHER_LAST_SUCCESS
+            .lock()
+            .unwrap()
+            .contains_key(&labels.values)
+    }
+
+    #[tokio::test]
+    async fn same_url_subscriptions_are_tracked_apart() {
+        let (get, post) = (payload("GET"), payload("POST"));
+        let (get_labels, post_labels) = (FetcherLabels::new(&get), FetcherLabels::new(&post));
+        let period = Duration::from_millis(10);
+        let get_fetcher = Arc::new(Fetcher::new(get, period));
+        let post_fetcher = Arc::new(Fetcher::new(post, period));
+        let timeout = Duration::from_secs(1);
+        tokio::time::timeout(timeout, Arc::clone(&get_fetcher).next())
+            .await
+            .unwrap();
+        tokio::time::timeout(timeout, Arc::clone(&post_fetcher).next())
+            .await
+            .unwrap();
+        assert!(is_tracked(&get_labels) && is_tracked(&post_labels));
+
+        post_fetcher.kill();
+        tokio::time::timeout(timeout, async {
+            while is_tracked(&post_labels) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(is_tracked(&get_labels));
+        let label = |metric: &Metric, name: &str| {
+            let label = metric.get_label().iter().find(|x| x.get_name() == name);
+            label.unwrap().get_value().to_string()
+        };
+        let periods = FETCHER_PERIOD_GAUGE.collect()[0]
+            .get_metric()
+            .iter()
+            .filter(|x| label(x, "url") == "http://millis.local/staleness")
+            .map(|x| label(x, "method"))
+            .collect::<Vec<_>>();
+        assert_eq!(periods, ["GET"]);
+    }
+}
@@ -0,0 +1,295 @@
+//! Native parsing of orderbook payloads into contiguous arrays of levels.
+//!
+//! The position of the levels in a payload is described by an [`OrderbookLayout`], given from
+//! Lua as a JSON object of dotted paths(see [`JsonDocument::find`]), e.g.
+//! `{"bids": "data.1.bids", "asks": "data.1.asks", "price": "1", "quantity": "2"}` where
+//! `price` and `quantity` are relative to each level.
+
+use std::{
+    cmp::Reverse,
+    ffi::{c_char, CString},
+    mem::ManuallyDrop,
+    ptr::{self, null_mut},
+};
+
+use grasshopper_macros::lua_export;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{event::ResponsePayload, json::JsonDocument, lua_decimal::FfiDecimal, LuaStr};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct OrderbookLayout {
+    bids: String,
+    asks: String,
+    price: String,
+    quantity: String,
+    /// Parse error of the spec, in which case the paths are empty.
+    #[serde(skip)]
+    error: Option<CString>,
+}
+
+#[repr(C)]
+pub struct FfiLevel {
+    price: FfiDecimal,
+    quantity: FfiDecimal,
+}
+
+/// Levels sorted from the best price, which must be freed with [`free_orderbook`].
+#[repr(C)]
+pub struct FfiOrderbook {
+    bids: *mut FfiLevel,
+    bids_len: usize,
+    bids_cap: usize,
+    asks: *mut FfiLevel,
+    asks_len: usize,
+    asks_cap: usize,
+    /// Null unless the payload cannot be parsed, in which case there are no levels.
+    error: *mut c_char,
+}
+
+impl FfiOrderbook {
+    fn new(bids: Vec<FfiLevel>, asks: Vec<FfiLevel>, error: Option<String>) -> Self {
+        let mut bids = ManuallyDrop::new(bids);
+        let mut asks = ManuallyDrop::new(asks);
+        Self {
+            bids: bids.as_mut_ptr(),
+            bids_len: bids.len(),
+            bids_cap: bids.capacity(),
+            asks: asks.as_mut_ptr(),
+            asks_len: asks.len(),
+            asks_cap: asks.capacity(),
+            error: error.map_or(null_mut(), |x| CString::new(x).unwrap().into_raw()),
+        }
+    }
+}
+
+/// Reads the levels at `path`, sorted by price in descending order if `descending` is set.
+fn parse_levels(
+    doc: &JsonDocument,
+    layout: &OrderbookLayout,
+    path: &str,
+    descending: bool,
+) -> Result<Vec<FfiLevel>, String> {
+    let items = doc
+        .find(0, path)
+        .and_then(|x| doc.items(x))
+        .ok_or_else(|| format!("no array at {path}"))?;
+    let mut levels = Vec::with_capacity(items.len());
+    for (i, &item) in items.iter().enumerate() {
+        let field = |field_path: &str| {
+            doc.find(item, field_path)
+                .and_then(|x| doc.decimal(x))
+                .ok_or_else(|| format!("invalid level at {path}.{}", i + 1))
+        };
+        let price = field(&layout.price)?;
+        let quantity = field(&layout.quantity)?;
+        if price <= Decimal::ZERO || quantity.is_sign_negative() {
+            return Err(format!(
+                "invalid level at {path}.{}: {price} x {quantity}",
+                i + 1
+            ));
+        }
+        levels.push((price, quantity));
+    }
+    if descending {
+        levels.sort_by_key(|x| Reverse(x.0));
+    } else {
+        levels.sort_by_key(|x| x.0);
+    }
+    Ok(levels
+        .into_iter()
+        .map(|(price, quantity)| FfiLevel {
+            price: price.into(),
+            quantity: quantity.into(),
+        })
+        .collect())
+}
+
+/// Parses the layout spec once, to be reused for every payload of a subscription. Check
+/// [`orderbook_layout_error`] before using it.
+#[lua_export]
+pub extern "C-unwind" fn orderbook_layout_new(spec: LuaStr) -> *mut OrderbookLayout {
+    let spec = unsafe { spec.as_str() };
+    let layout =
+        serde_json::from_str::<OrderbookLayout>(spec).unwrap_or_else(|e| OrderbookLayout {
+            error: Some(CString::new(e.to_string()).unwrap()),
+            ..Default::default()
+        });
+    Box::into_raw(Box::new(layout))
+}
+
+/// Returns the parse error of the spec, or null if the layout is valid.
+#[lua_export]
+pub unsafe extern "C-unwind" fn orderbook_layout_error(
+    layout: *const OrderbookLayout,
+) -> *const c_char {
+    match &(*layout).error {
+        Some(e) => e.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[lua_export]
+pub unsafe extern "C-unwind" fn orderbook_layout_free(layout: *mut OrderbookLayout) {
+    drop(Box::from_raw(layout))
+}
+
+/// Parses the content of `payload` as laid out by `layout`. Bids are sorted in descending order
+/// of prices and asks in ascending order. Levels must have positive prices and non-negative
+/// quantities. Crossed books are returned as they are, since exchanges may publish them
+/// transiently.
+#[lua_export]
+pub unsafe extern "C-unwind" fn parse_orderbook(
+    payload: *const ResponsePayload,
+    layout: *const OrderbookLayout,
+) -> FfiOrderbook {
+    let layout = &*layout;
+    if let Some(e) = &layout.error {
+        let error = format!("invalid orderbook layout: {}", e.to_string_lossy());
+        return FfiOrderbook::new(Vec::new(), Vec::new(), Some(error));
+    }
+    let result = (*payload)
+        .content()
+        .ok_or_else(|| "empty payload".to_string())
+        .and_then(JsonDocument::parse)
+        .and_then(|doc| {
+            Ok((
+                parse_levels(&doc, layout, &layout.bids, true)?,
+                parse_levels(&doc, layout, &layout.asks, false)?,
+            ))
+        });
+    match result {
+        Ok((bids, asks)) => FfiOrderbook::new(bids, asks, None),
+        Err(e) => FfiOrderbook::new(Vec::new(), Vec::new(), Some(e)),
+    }
+}
+
+#[lua_export]
+pub unsafe extern "C-unwind" fn free_orderbook(orderbook: FfiOrderbook) {
+    drop(Vec::from_raw_parts(
+        orderbook.bids,
+        orderbook.bids_len,
+        orderbook.bids_cap,
+    ));
+    drop(Vec::from_raw_parts(
+        orderbook.asks,
+        orderbook.asks_len,
+        orderbook.asks_cap,
+    ));
+    if !orderbook.error.is_null() {
+        drop(CString::from_raw(orderbook.error));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use rust_decimal::Decimal;
+
+    use super::{orderbook_layout_error, orderbook_layout_new, parse_levels, OrderbookLayout};
+    use crate::{json::JsonDocument, LuaStr};
+
+    const LAYOUT: &str = r#"{"bids": "bids", "asks": "asks", "price": "1", "quantity": "2"}"#;
+
+    fn layout(spec: &str) -> Result<Box<OrderbookLayout>, String> {
+        let layout =
+            unsafe { Box::from_raw(orderbook_layout_new(LuaStr::from_bytes(spec.as_bytes()))) };
+        let error = unsafe { orderbook_layout_error(&*layout) };
+        if error.is_null() {
+            Ok(layout)
+        } else {
+            Err(unsafe { CStr::from_ptr(error) }
+                .to_string_lossy()
+                .into_owned())
+        }
+    }
+
+    fn levels(
+        payload: &str,
+        path: &str,
+        descending: bool,
+    ) -> Result<Vec<(String, String)>, String> {
+        let doc = JsonDocument::parse(payload.as_bytes()).unwrap();
+        let layout = layout(LAYOUT).unwrap();
+        Ok(parse_levels(&doc, &layout, path, descending)?
+            .into_iter()
+            .map(|x| {
+                (
+                    Decimal::from(x.price).to_string(),
+                    Decimal::from(x.quantity).to_string(),
+                )
+            })
+            .collect())
+    }
+
+    fn pairs(x: &[(&str, &str)]) -> Vec<(String, String)> {
+        x.iter()
+            .map(|(p, q)| (p.to_string(), q.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn unsorted_levels() {
+        let payload = r#"{"bids": [["1", "1"], ["3", "2"], ["2", "0"]], "asks": [[5, 1], [4, 2]]}"#;
+        assert_eq!(
+            levels(payload, "bids", true),
+            Ok(pairs(&[("3", "2"), ("2", "0"), ("1", "1")]))
+        );
+        assert_eq!(
+            levels(payload, "asks", false),
+            Ok(pairs(&[("4", "2"), ("5", "1")]))
+        );
+    }
+
+    #[test]
+    fn crossed_levels() {
+        let payload =
+            r#"{"bids": [["101", "1"], ["99", "1"]], "asks": [["100", "1"], ["102", "1"]]}"#;
+        assert_eq!(
+            levels(payload, "bids", true),
+            Ok(pairs(&[("101", "1"), ("99", "1")]))
+        );
+        assert_eq!(
+            levels(payload, "asks", false),
+            Ok(pairs(&[("100", "1"), ("102", "1")]))
+        );
+    }
+
+    #[test]
+    fn malformed_levels() {
+        let error = |payload: &str| levels(payload, "bids", true).unwrap_err();
+        assert_eq!(error(r#"{"asks": []}"#), "no array at bids");
+        assert_eq!(error(r#"{"bids": {"1": "1"}}"#), "no array at bids");
+        assert_eq!(
+            error(r#"{"bids": [["1", "1"], ["2"]]}"#),
+            "invalid level at bids.2"
+        );
+        assert_eq!(
+            error(r#"{"bids": [["x", "1"]]}"#),
+            "invalid level at bids.1"
+        );
+        assert_eq!(
+            error(r#"{"bids": [[null, "1"]]}"#),
+            "invalid level at bids.1"
+        );
+        assert_eq!(error(r#"{"bids": ["1"]}"#), "invalid level at bids.1");
+        assert_eq!(
+            error(r#"{"bids": [["0", "1"]]}"#),
+            "invalid level at bids.1: 0 x 1"
+        );
+        assert_eq!(
+            error(r#"{"bids": [["1", "-1"]]}"#),
+            "invalid level at bids.1: 1 x -1"
+        );
+    }
+
+    #[test]
+    fn invalid_layout() {
+        assert!(layout(r#"{"bids": "b", "asks": "a", "price": "1"}"#)
+            .unwrap_err()
+            .contains("missing field `quantity`"));
+        assert!(layout("bids").is_err());
+    }
+}
@@ -0,0 +1,825 @@
+//! Paper trading against the orderbooks fetched by subscriptions.
+//!
+//! If `GRASSHOPPER_PAPER_TRADING` is set, signed requests never reach the exchange. Binance spot
+//! and futures order, balance, order list and position endpoints are answered by a simulator,
+//! and other signed endpoints fail with an error response. The simulator fills orders against
+//! the latest depth payloads(`/api/v3/depth`, `/fapi/v1/depth`) of REST subscriptions, without
+//! fees or margin requirements.
+//!
+//! Initial balances are read from `GRASSHOPPER_PAPER_BALANCES`, e.g.
+//! `{"spot": {"USDT": "10000"}, "swap": {"USDT": "10000"}}`.
+
+use std::{collections::HashMap, env::var, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use reqwest::{Method, Url};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::{
+    clock,
+    event::{RequestPayload, ResponsePayload},
+};
+
+static SIMULATOR: Lazy<Option<Mutex<Simulator>>> = Lazy::new(|| {
+    var("GRASSHOPPER_PAPER_TRADING").ok()?;
+    let balances: HashMap<MarketType, HashMap<String, Decimal>> =
+        match var("GRASSHOPPER_PAPER_BALANCES") {
+            Ok(x) => serde_json::from_str(&x).expect("cannot parse paper trading balances"),
+            Err(_) => HashMap::new(),
+        };
+    info!(?balances, "paper trading enabled");
+    Some(Mutex::new(Simulator::new(balances)))
+});
+
+/// Quote assets to split symbols with, in the order of precedence. Orders of symbols quoted in
+/// other assets are rejected.
+const QUOTE_ASSETS: &[&str] = &[
+    "USDT", "USDC", "FDUSD", "BUSD", "TUSD", "BTC", "ETH", "BNB", "EUR", "TRY",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum MarketType {
+    Spot,
+    Swap,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Order {
+    symbol: String,
+    order_id: u64,
+    price: Decimal,
+    orig_qty: Decimal,
+    executed_qty: Decimal,
+    /// Quote amount of the fills.
+    cum_quote: Decimal,
+    status: OrderStatus,
+    #[serde(rename = "type")]
+    order_type: &'static str,
+    side: Side,
+    update_time: u64,
+    #[serde(skip)]
+    market_type: MarketType,
+    /// Base and quote assets of the symbol.
+    #[serde(skip)]
+    assets: (String, String),
+}
+
+impl Order {
+    fn remaining(&self) -> Decimal {
+        self.orig_qty - self.executed_qty
+    }
+
+    fn is_open(&self) -> bool {
+        matches!(self.status, OrderStatus::New | OrderStatus::PartiallyFilled)
+    }
+
+    fn to_response(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap();
+        let avg_price = if self.executed_qty.is_zero() {
+            Decimal::ZERO
+        } else {
+            self.cum_quote / self.executed_qty
+        };
+        value["avgPrice"] = json!(avg_price);
+        value["cummulativeQuoteQty"] = json!(self.cum_quote);
+        value["transactTime"] = json!(self.update_time);
+        value["timeInForce"] = json!("GTC");
+        value
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Book {
+    bids: Vec<(Decimal, Decimal)>,
+    asks: Vec<(Decimal, Decimal)>,
+}
+
+#[derive(Debug, Default)]
+struct Balance {
+    free: Decimal,
+    locked: Decimal,
+}
+
+#[derive(Debug, Default)]
+struct Position {
+    amount: Decimal,
+    entry_price: Decimal,
+}
+
+/// An error response in the shape of Binance errors.
+struct Rejection {
+    status: u16,
+    code: i64,
+    msg: String,
+}
+
+impl Rejection {
+    fn new(code: i64, msg: impl Into<String>) -> Self {
+        Self {
+            status: 400,
+            code,
+            msg: msg.into(),
+        }
+    }
+}
+
+struct Simulator {
+    books: HashMap<(MarketType, String), Book>,
+    balances: HashMap<MarketType, HashMap<String, Balance>>,
+    positions: HashMap<String, Position>,
+    orders: Vec<Order>,
+    last_order_id: u64,
+}
+
+impl Simulator {
+    fn new(balances: HashMap<MarketType, HashMap<String, Decimal>>) -> Self {
+        let balances = balances
+            .into_iter()
+            .map(|(market_type, x)| {
+                let x = x
+                    .into_iter()
+                    .map(|(asset, free)| {
+                        (
+                            asset,
+                            Balance {
+                                free,
+                                locked: Decimal::ZERO,
+                            },
+                        )
+                    })
+                    .collect();
+                (market_type, x)
+            })
+            .collect();
+        Self {
+            books: HashMap::new(),
+            balances,
+            positions: HashMap::new(),
+            orders: Vec::new(),
+            last_order_id: 0,
+        }
+    }
+
+    fn balance(&mut self, market_type: MarketType, asset: &str) -> &mut Balance {
+        self.balances
+            .entry(market_type)
+            .or_default()
+            .entry(asset.to_string())
+            .or_default()
+    }
+
+    fn observe(&mut self, market_type: MarketType, symbol: String, book: Book) {
+        self.books.insert((market_type, symbol.clone()), book);
+        for i in 0..self.orders.len() {
+            let order = &self.orders[i];
+            if order.market_type == market_type && order.symbol == symbol && order.is_open() {
+                // resting orders are makers, filled at their own price
+                let price = order.price;
+                for (_, qty) in self.take(i, Some(price)) {
+                    self.fill(i, price, qty);
+                }
+            }
+        }
+    }
+
+    /// Takes liquidity for the remaining quantity of the order up to `limit`, and returns the
+    /// price and quantity of each level taken.
+    fn take(&mut self, index: usize, limit: Option<Decimal>) -> Vec<(Decimal, Decimal)> {
+        let order = &self.orders[index];
+        let Some(book) = self
+            .books
+            .get_mut(&(order.market_type, order.symbol.clone()))
+        else {
+            return Vec::new();
+        };
+        let levels = match order.side {
+            Side::Buy => &mut book.asks,
+            Side::Sell => &mut book.bids,
+        };
+        let mut remaining = order.remaining();
+        let mut fills = Vec::new();
+        for (price, qty) in levels.iter_mut() {
+            if remaining.is_zero() {
+                break;
+            }
+            let crosses = match (order.side, limit) {
+                (_, None) => true,
+                (Side::Buy, Some(limit)) => *price <= limit,
+                (Side::Sell, Some(limit)) => *price >= limit,
+            };
+            if !crosses {
+                break;
+            }
+            let taken = remaining.min(*qty);
+            *qty -= taken;
+            remaining -= taken;
+            fills.push((*price, taken));
+        }
+        levels.retain(|(_, qty)| !qty.is_zero());
+        fills
+    }
+
+    fn fill(&mut self, index: usize, price: Decimal, qty: Decimal) {
+        let order = &mut self.orders[index];
+        order.executed_qty += qty;
+        order.cum_quote += price * qty;
+        order.update_time = epoch_millis();
+        order.status = if order.remaining().is_zero() {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+        let order = order.clone();
+        debug!(order.symbol, order.order_id, %price, %qty, "paper order filled");
+
+        match order.market_type {
+            MarketType::Spot => {
+                let (base, quote) = (order.assets.0.as_str(), order.assets.1.as_str());
+                match order.side {
+                    Side::Buy => {
+                        // market buys do not lock funds in advance
+                        let locked = if order.order_type == "LIMIT" {
+                            order.price * qty
+                        } else {
+                            Decimal::ZERO
+                        };
+                        let quote = self.balance(MarketType::Spot, quote);
+                        quote.locked -= locked;
+                        quote.free += locked - price * qty;
+                        self.balance(MarketType::Spot, base).free += qty;
+                    }
+                    Side::Sell => {
+                        let base = self.balance(MarketType::Spot, base);
+                        if order.order_type == "LIMIT" {
+                            base.locked -= qty;
+                        } else {
+                            base.free -= qty;
+                        }
+                        self.balance(MarketType::Spot, quote).free += price * qty;
+                    }
+                }
+            }
+            MarketType::Swap => {
+                let delta = match order.side {
+                    Side::Buy => qty,
+                    Side::Sell => -qty,
+                };
+                let position = self.positions.entry(order.symbol.clone()).or_default();
+                let mut realized = Decimal::ZERO;
+                if position.amount.is_zero()
+                    || position.amount.is_sign_positive() == (delta > Decimal::ZERO)
+                {
+                    let amount = position.amount + delta;
+                    position.entry_price =
+                        (position.entry_price * position.amount + price * delta) / amount;
+                    position.amount = amount;
+                } else {
+                    let closed = delta.abs().min(position.amount.abs());
+                    let direction = if position.amount.is_sign_positive() {
+                        Decimal::ONE
+                    } else {
+                        Decimal::NEGATIVE_ONE
+                    };
+                    realized = (price - position.entry_price) * closed * direction;
+                    position.amount += delta;
+                    if position.amount.is_zero() {
+                        position.entry_price = Decimal::ZERO;
+                    } else if position.amount.is_sign_positive() != direction.is_sign_positive() {
+                        // flipped to the other side
+                        position.entry_price = price;
+                    }
+                }
+                self.balance(MarketType::Swap, &order.assets.1).free += realized;
+            }
+        }
+    }
+
+    fn place_order(
+        &mut self,
+        market_type: MarketType,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, Rejection> {
+        let param = |name: &str| {
+            params.get(name).ok_or_else(|| {
+                Rejection::new(-1102, format!("mandatory parameter '{name}' was not sent"))
+            })
+        };
+        let decimal = |name: &str| {
+            param(name)?
+                .parse::<Decimal>()
+                .map_err(|_| Rejection::new(-1100, format!("illegal characters found in '{name}'")))
+        };
+        let symbol = param("symbol")?.clone();
+        let side = match param("side")?.as_str() {
+            "BUY" => Side::Buy,
+            "SELL" => Side::Sell,
+            x => return Err(Rejection::new(-1117, format!("invalid side {x}"))),
+        };
+        let qty = decimal("quantity")?;
+        let (order_type, price) = match param("type")?.as_str() {
+            "LIMIT" => ("LIMIT", Some(decimal("price")?)),
+            "MARKET" => ("MARKET", None),
+            x => return Err(Rejection::new(-1116, format!("invalid order type {x}"))),
+        };
+        if qty <= Decimal::ZERO || price.is_some_and(|x| x <= Decimal::ZERO) {
+            return Err(Rejection::new(-1013, "invalid quantity or price"));
+        }
+        let Some((base, quote)) = split_symbol(&symbol) else {
+            return Err(Rejection::new(
+                -1121,
+                format!("quote asset of {symbol} is not supported in paper trading"),
+            ));
+        };
+        let assets = (base.to_string(), quote.to_string());
+        if !self.books.contains_key(&(market_type, symbol.clone())) {
+            return Err(Rejection::new(
+                -1,
+                format!("no orderbook of {symbol} has been fetched yet"),
+            ));
+        }
+
+        if market_type == MarketType::Spot {
+            let (asset, required) = match (side, price) {
+                (Side::Buy, Some(price)) => (&assets.1, price * qty),
+                (Side::Buy, None) => (&assets.1, self.market_cost(market_type, &symbol, qty)),
+                (Side::Sell, _) => (&assets.0, qty),
+            };
+            let balance = self.balance(market_type, asset);
+            if balance.free < required {
+                return Err(Rejection::new(
+                    -2010,
+                    "Account has insufficient balance for requested action.",
+                ));
+            }
+            if price.is_some() {
+                balance.free -= required;
+                balance.locked += required;
+            }
+        }
+
+        self.last_order_id += 1;
+        self.orders.push(Order {
+            symbol,
+            order_id: self.last_order_id,
+            price: price.unwrap_or_default(),
+            orig_qty: qty,
+            executed_qty: Decimal::ZERO,
+            cum_quote: Decimal::ZERO,
+            status: OrderStatus::New,
+            order_type,
+            side,
+            update_time: epoch_millis(),
+            market_type,
+            assets,
+        });
+        let index = self.orders.len() - 1;
+        for (price, qty) in self.take(index, price) {
+            self.fill(index, price, qty);
+        }
+        let order = &mut self.orders[index];
+        if order.order_type == "MARKET" && order.is_open() {
+            // unfilled remainder of market orders expires like IOC
+            order.status = OrderStatus::Canceled;
+        }
+        info!(?order, "paper order placed");
+        Ok(order.to_response())
+    }
+
+    /// Returns the quote amount to fill `qty` with the current asks.
+    fn market_cost(&self, market_type: MarketType, symbol: &str, qty: Decimal) -> Decimal {
+        let mut remaining = qty;
+        let mut cost = Decimal::ZERO;
+        for (price, level_qty) in &self.books[&(market_type, symbol.to_string())].asks {
+            let taken = remaining.min(*level_qty);
+            cost += price * taken;
+            remaining -= taken;
+        }
+        cost
+    }
+
+    fn cancel_order(
+        &mut self,
+        market_type: MarketType,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, Rejection> {
+        let symbol = params.get("symbol");
+        let order_id = params.get("orderId").and_then(|x| x.parse::<u64>().ok());
+        let Some(order) = self.orders.iter_mut().find(|x| {
+            x.market_type == market_type
+                && Some(&x.symbol) == symbol
+                && Some(x.order_id) == order_id
+                && x.is_open()
+        }) else {
+            return Err(Rejection::new(-2011, "Unknown order sent."));
+        };
+        order.status = OrderStatus::Canceled;
+        order.update_time = epoch_millis();
+        let order = order.clone();
+        if market_type == MarketType::Spot && order.order_type == "LIMIT" {
+            let (asset, unlocked) = match order.side {
+                Side::Buy => (&order.assets.1, order.price * order.remaining()),
+                Side::Sell => (&order.assets.0, order.remaining()),
+            };
+            let balance = self.balance(market_type, asset);
+            balance.locked -= unlocked;
+            balance.free += unlocked;
+        }
+        info!(?order, "paper order canceled");
+        Ok(order.to_response())
+    }
+
+    fn orders(&self, market_type: MarketType, params: &HashMap<String, String>) -> Value {
+        Value::Array(
+            self.orders
+                .iter()
+                .filter(|x| {
+                    x.market_type == market_type
+                        && params.get("symbol").is_none_or(|s| *s == x.symbol)
+                })
+                .map(Order::to_response)
+                .collect(),
+        )
+    }
+
+    fn spot_account(&self) -> Value {
+        let balances = self
+            .balances
+            .get(&MarketType::Spot)
+            .into_iter()
+            .flatten()
+            .map(|(asset, x)| json!({ "asset": asset, "free": x.free, "locked": x.locked }))
+            .collect::<Vec<_>>();
+        json!({ "balances": balances })
+    }
+
+    fn futures_balance(&self) -> Value {
+        Value::Array(
+            self.balances
+                .get(&MarketType::Swap)
+                .into_iter()
+                .flatten()
+                .map(|(asset, x)| {
+                    json!({
+                        "asset": asset,
+                        "balance": x.free,
+                        "availableBalance": x.free,
+                        "maxWithdrawAmount": x.free,
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    fn position_risk(&self) -> Value {
+        Value::Array(
+            self.positions
+                .iter()
+                .map(|(symbol, x)| {
+                    json!({
+                        "symbol": symbol,
+                        "positionAmt": x.amount,
+                        "entryPrice": x.entry_price,
+                        "positionSide": "BOTH",
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    fn handle(
+        &mut self,
+        method: &Method,
+        path: &str,
+        params: &HashMap<String, String>,
+    ) -> Result<Value, Rejection> {
+        match (method, path) {
+            (&Method::POST, "/api/v3/order") => self.place_order(MarketType::Spot, params),
+            (&Method::POST, "/fapi/v1/order") => self.place_order(MarketType::Swap, params),
+            (&Method::DELETE, "/api/v3/order") => self.cancel_order(MarketType::Spot, params),
+            (&Method::DELETE, "/fapi/v1/order") => self.cancel_order(MarketType::Swap, params),
+            (&Method::GET, "/api/v3/allOrders") => Ok(self.orders(MarketType::Spot, params)),
+            (&Method::GET, "/fapi/v1/orders") => Ok(self.orders(MarketType::Swap, params)),
+            (&Method::GET, "/api/v3/account") => Ok(self.spot_account()),
+            (&Method::GET, "/fapi/v2/balance") => Ok(self.futures_balance()),
+            (&Method::GET, "/fapi/v2/positionRisk") => Ok(self.position_risk()),
+            _ => Err(Rejection {
+                status: 501,
+                code: -1,
+                msg: format!("{method} {path} is not supported in paper trading"),
+            }),
+        }
+    }
+}
+
+fn epoch_millis() -> u64 {
+    clock::epoch().as_millis() as u64
+}
+
+/// Splits a symbol into its base and quote assets, or returns [`None`] if the quote asset is
+/// unknown.
+fn split_symbol(symbol: &str) -> Option<(&str, &str)> {
+    QUOTE_ASSETS.iter().find_map(|quote| {
+        let base = symbol.strip_suffix(quote)?;
+        (!base.is_empty()).then_some((base, *quote))
+    })
+}
+
+fn query_params(url: &Url) -> HashMap<String, String> {
+    url.query_pairs().into_owned().collect()
+}
+
+/// Updates the orderbook if `response` is a depth payload, filling crossed orders.
+pub(crate) fn observe(response: &ResponsePayload) {
+    let Some(simulator) = SIMULATOR.as_ref() else {
+        return;
+    };
+    let Ok(url) = Url::parse(response.url()) else {
+        return;
+    };
+    let market_type = match url.path() {
+        "/api/v3/depth" => MarketType::Spot,
+        "/fapi/v1/depth" => MarketType::Swap,
+        _ => return,
+    };
+    let Some(symbol) = query_params(&url).remove("symbol") else {
+        return;
+    };
+    let Some(book) = response
+        .content()
+        .and_then(|x| serde_json::from_slice::<Book>(x).ok())
+    else {
+        return;
+    };
+    simulator.lock().unwrap().observe(market_type, symbol, book);
+}
+
+/// Answers a signed request from the simulator. Returns [`None`] if paper trading is disabled
+/// or the request is not signed.
+pub(crate) fn respond(payload: &RequestPayload) -> Option<ResponsePayload> {
+    let simulator = SIMULATOR.as_ref()?;
+    if payload.sign != Some(true) {
+        return None;
+    }
+    let result = match Url::parse(&payload.url) {
+        Ok(url) => {
+            simulator
+                .lock()
+                .unwrap()
+                .handle(&payload.method, url.path(), &query_params(&url))
+        }
+        Err(err) => Err(Rejection::new(-1, format!("cannot parse URL: {err}"))),
+    };
+    let (status, content) = match result {
+        Ok(x) => (200, x),
+        Err(e) => {
+            warn!(%payload.url, %payload.method, e.code, e.msg, "paper trading request rejected");
+            (e.status, json!({ "code": e.code, "msg": e.msg }))
+        }
+    };
+    Some(
+        ResponsePayload::from_string(&payload.url, content.to_string())
+            .with_status(status)
+            .with_env_suffix(payload.env_suffix.clone()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use reqwest::Method;
+    use rust_decimal::Decimal;
+    use serde_json::Value;
+
+    use super::{Book, MarketType, Simulator};
+    use crate::clock;
+
+    fn d(x: &str) -> Decimal {
+        x.parse().unwrap()
+    }
+
+    fn book(json: &str) -> Book {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn simulator() -> Simulator {
+        let balances = [
+            (MarketType::Spot, [("USDT".to_string(), d("1000"))].into()),
+            (MarketType::Swap, [("USDT".to_string(), d("1000"))].into()),
+        ];
+        let mut simulator = Simulator::new(balances.into());
+        let depth = r#"{"bids":[["99","1"],["98","2"]],"asks":[["101","1"],["102","2"]]}"#;
+        simulator.observe(MarketType::Spot, "BTCUSDT".to_string(), book(depth));
+        simulator.observe(MarketType::Swap, "BTCUSDT".to_string(), book(depth));
+        simulator
+    }
+
+    fn handle(
+        simulator: &mut Simulator,
+        method: Method,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Value, i64> {
+        let params = params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        simulator.handle(&method, path, &params).map_err(|x| x.code)
+    }
+
+    fn order_params(
+        side: &str,
+        order_type: &str,
+        qty: &str,
+        price: Option<&str>,
+    ) -> Vec<(&'static str, String)> {
+        let mut params = vec![
+            ("symbol", "BTCUSDT".to_string()),
+            ("side", side.to_string()),
+            ("type", order_type.to_string()),
+            ("quantity", qty.to_string()),
+        ];
+        if let Some(price) = price {
+            params.push(("price", price.to_string()));
+        }
+        params
+    }
+
+    fn place(
+        simulator: &mut Simulator,
+        path: &str,
+        params: &[(&'static str, String)],
+    ) -> Result<Value, i64> {
+        let params = params
+            .iter()
+            .map(|(k, v)| (*k, v.as_str()))
+            .collect::<Vec<_>>();
+        handle(simulator, Method::POST, path, &params)
+    }
+
+    fn spot_balance(simulator: &Simulator, asset: &str) -> (Decimal, Decimal) {
+        let x = &simulator.balances[&MarketType::Spot][asset];
+        (x.free, x.locked)
+    }
+
+    #[test]
+    fn market_order_takes_levels() {
+        let _guard = clock::lock_virtual();
+        let mut simulator = simulator();
+        let order = place(
+            &mut simulator,
+            "/api/v3/order",
+            &order_params("BUY", "MARKET", "2", None),
+        )
+        .unwrap();
+        assert_eq!(order["status"], "FILLED");
+        assert_eq!(order["executedQty"], "2");
+        assert_eq!(order["cummulativeQuoteQty"], "203");
+        assert_eq!(spot_balance(&simulator, "USDT"), (d("797"), d("0")));
+        assert_eq!(spot_balance(&simulator, "BTC"), (d("2"), d("0")));
+
+        // the remainder beyond the book expires
+        simulator.balance(MarketType::Spot, "BTC").free = d("10");
+        let order = place(
+            &mut simulator,
+            "/api/v3/order",
+            &order_params("SELL", "MARKET", "5", None),
+        )
+        .unwrap();
+        assert_eq!(order["status"], "CANCELED");
+        assert_eq!(order["executedQty"], "3");
+    }
+
+    #[test]
+    fn resting_order_fills_on_observe() {
+        let _guard = clock::lock_virtual();
+        let mut simulator = simulator();
+        let order = place(
+            &mut simulator,
+            "/api/v3/order",
+            &order_params("BUY", "LIMIT", "1", Some("100")),
+        )
+        .unwrap();
+        assert_eq!(order["status"], "NEW");
+        assert_eq!(spot_balance(&simulator, "USDT"), (d("900"), d("100")));
+
+        let crossed = r#"{"bids":[["98","1"]],"asks":[["99.5","0.4"],["100","1"]]}"#;
+        simulator.observe(MarketType::Spot, "BTCUSDT".to_string(), book(crossed));
+        let orders = handle(&mut simulator, Method::GET, "/api/v3/allOrders", &[]).unwrap();
+        // makers are filled at their own price, over both crossing levels
+        assert_eq!(orders[0]["status"], "FILLED");
+        assert_eq!(orders[0]["cummulativeQuoteQty"], "100.0");
+        assert_eq!(spot_balance(&simulator, "USDT"), (d("900"), d("0")));
+        assert_eq!(spot_balance(&simulator, "BTC"), (d("1"), d("0")));
+    }
+
+    #[test]
+    fn cancel_unlocks_balance() {
+        let _guard = clock::lock_virtual();
+        let mut simulator = simulator();
+        let order = place(
+            &mut simulator,
+            "/api/v3/order",
+            &order_params("BUY", "LIMIT", "2", Some("50")),
+        )
+        .unwrap();
+        let order_id = order["orderId"].to_string();
+        let params = [("symbol", "BTCUSDT"), ("orderId", order_id.as_str())];
+        let order = handle(&mut simulator, Method::DELETE, "/api/v3/order", &params).unwrap();
+        assert_eq!(order["status"], "CANCELED");
+        assert_eq!(spot_balance(&simulator, "USDT"), (d("1000"), d("0")));
+        assert_eq!(
+            handle(&mut simulator, Method::DELETE, "/api/v3/order", &params),
+            Err(-2011)
+        );
+    }
+
+    #[test]
+    fn swap_position_flips_and_realizes_pnl() {
+        let _guard = clock::lock_virtual();
+        let mut simulator = simulator();
+        place(
+            &mut simulator,
+            "/fapi/v1/order",
+            &order_params("BUY", "MARKET", "1", None),
+        )
+        .unwrap();
+        let book = book(r#"{"bids":[["111","5"]],"asks":[["112","5"]]}"#);
+        simulator.observe(MarketType::Swap, "BTCUSDT".to_string(), book);
+        place(
+            &mut simulator,
+            "/fapi/v1/order",
+            &order_params("SELL", "MARKET", "3", None),
+        )
+        .unwrap();
+
+        let positions = handle(&mut simulator, Method::GET, "/fapi/v2/positionRisk", &[]).unwrap();
+        assert_eq!(positions[0]["positionAmt"], "-2");
+        assert_eq!(positions[0]["entryPrice"], "111");
+        let balances = handle(&mut simulator, Method::GET, "/fapi/v2/balance", &[]).unwrap();
+        assert_eq!(balances[0]["balance"], "1010");
+    }
+
+    #[test]
+    fn invalid_orders_are_rejected() {
+        let _guard = clock::lock_virtual();
+        let mut simulator = simulator();
+        let mut unknown_quote = order_params("BUY", "MARKET", "1", None);
+        unknown_quote[0].1 = "BTCXYZ".to_string();
+        assert_eq!(
+            place(&mut simulator, "/api/v3/order", &unknown_quote),
+            Err(-1121)
+        );
+        let mut no_book = order_params("BUY", "MARKET", "1", None);
+        no_book[0].1 = "ETHUSDT".to_string();
+        assert_eq!(place(&mut simulator, "/api/v3/order", &no_book), Err(-1));
+        assert_eq!(
+            place(
+                &mut simulator,
+                "/api/v3/order",
+                &order_params("BUY", "LIMIT", "20", Some("100"))
+            ),
+            Err(-2010)
+        );
+        assert_eq!(
+            place(
+                &mut simulator,
+                "/api/v3/order",
+                &order_params("BUY", "STOP", "1", None)
+            ),
+            Err(-1116)
+        );
+        assert_eq!(
+            handle(&mut simulator, Method::GET, "/api/v3/openOrders", &[]),
+            Err(-1)
+        );
+        assert!(
+            handle(&mut simulator, Method::GET, "/api/v3/allOrders", &[])
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
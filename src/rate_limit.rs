@@ -0,0 +1,239 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use grasshopper_macros::lua_export;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use tracing::{debug, info};
+
+use crate::LuaStr;
+
+static BUCKETS: Lazy<Mutex<HashMap<String, TokenBucket>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A rate limit of `limit` weights per `interval_ms` milliseconds.
+#[derive(Debug, Deserialize)]
+struct RateLimitConfig {
+    /// Host of the request URL, or a custom `rate_limit_key` of requests.
+    key: String,
+    limit: f64,
+    interval_ms: f64,
+    /// Whether sends exceeding the limit fail immediately instead of waiting.
+    #[serde(default)]
+    fail_fast: bool,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_ms: f64,
+    last_refill: Instant,
+    fail_fast: bool,
+}
+
+impl TokenBucket {
+    fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            capacity: config.limit,
+            tokens: config.limit,
+            refill_per_ms: config.limit / config.interval_ms,
+            last_refill: Instant::now(),
+            fail_fast: config.fail_fast,
+        }
+    }
+
+    /// Takes `weight` tokens, or returns how long it takes until they become available.
+    fn try_acquire(&mut self, weight: f64, now: Instant) -> Result<(), Duration> {
+        let elapsed_ms = now.duration_since(self.last_refill).as_secs_f64() * 1000.0;
+        self.tokens = (self.tokens + elapsed_ms * self.refill_per_ms).min(self.capacity);
+        self.last_refill = now;
+
+        // requests heavier than the whole bucket would never be admitted otherwise
+        let weight = weight.min(self.capacity);
+        if self.tokens >= weight {
+            self.tokens -= weight;
+            Ok(())
+        } else {
+            let wait_ms = (weight - self.tokens) / self.refill_per_ms;
+            Err(Duration::from_secs_f64(wait_ms / 1000.0))
+        }
+    }
+}
+
+/// Returned by [`acquire`] when a fail-fast bucket is exhausted.
+#[derive(Debug)]
+pub(crate) struct RateLimited {
+    key: String,
+    wait: Duration,
+}
+
+impl Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rate limit of {} exceeded, available in {:?}",
+            self.key, self.wait
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Takes `weight` from the bucket of `key`, waiting until it is available. Keys without a
+/// configured bucket are not limited.
+///
+/// If `fail_fast` is set and the bucket is configured to fail fast, returns [`RateLimited`]
+/// instead of waiting.
+pub(crate) async fn acquire(key: &str, weight: f64, fail_fast: bool) -> Result<(), RateLimited> {
+    loop {
+        let wait = {
+            let mut buckets = BUCKETS.lock().unwrap();
+            let Some(bucket) = buckets.get_mut(key) else {
+                return Ok(());
+            };
+            match bucket.try_acquire(weight, Instant::now()) {
+                Ok(()) => return Ok(()),
+                Err(wait) if fail_fast && bucket.fail_fast => {
+                    return Err(RateLimited {
+                        key: key.to_string(),
+                        wait,
+                    })
+                }
+                Err(wait) => wait,
+            }
+        };
+        debug!(key, ?wait, "delaying request due to rate limit");
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Returns the weight limit per interval of the bucket of `key`, if configured.
+pub(crate) fn capacity(key: &str) -> Option<f64> {
+    BUCKETS.lock().unwrap().get(key).map(|x| x.capacity)
+}
+
+/// Outcome of [`configure_rate_limit`].
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateLimitStatus {
+    Ok = 0,
+    /// The config is not valid JSON of a [`RateLimitConfig`].
+    InvalidConfig = 1,
+    /// The limit or interval is not positive.
+    NonPositive = 2,
+}
+
+/// Configures(or replaces) a rate limit bucket, which starts full.
+#[lua_export]
+pub extern "C-unwind" fn configure_rate_limit(config: LuaStr) -> RateLimitStatus {
+    let Ok(config) = serde_json::from_str::<RateLimitConfig>(unsafe { config.as_str() }) else {
+        return RateLimitStatus::InvalidConfig;
+    };
+    // also rejects NaN
+    if !(config.limit > 0.0 && config.interval_ms > 0.0) {
+        return RateLimitStatus::NonPositive;
+    }
+    info!(?config, "rate limit configured");
+    BUCKETS
+        .lock()
+        .unwrap()
+        .insert(config.key.clone(), TokenBucket::new(&config));
+    RateLimitStatus::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{
+        acquire, capacity, configure_rate_limit, RateLimitConfig, RateLimitStatus, TokenBucket,
+    };
+    use crate::LuaStr;
+
+    fn bucket(limit: f64, interval_ms: f64) -> TokenBucket {
+        TokenBucket::new(&RateLimitConfig {
+            key: String::new(),
+            limit,
+            interval_ms,
+            fail_fast: false,
+        })
+    }
+
+    fn configure(config: &str) -> RateLimitStatus {
+        configure_rate_limit(LuaStr::from_bytes(config.as_bytes()))
+    }
+
+    #[test]
+    fn refills_over_time_up_to_capacity() {
+        let mut bucket = bucket(10.0, 1000.0);
+        let start = bucket.last_refill;
+        assert_eq!(bucket.try_acquire(10.0, start), Ok(()));
+        assert_eq!(
+            bucket.try_acquire(1.0, start),
+            Err(Duration::from_millis(100))
+        );
+
+        let half = start + Duration::from_millis(500);
+        assert_eq!(bucket.try_acquire(5.0, half), Ok(()));
+        assert!(bucket.try_acquire(1.0, half).is_err());
+
+        // idle time beyond the interval does not overfill the bucket
+        let later = half + Duration::from_secs(60);
+        assert_eq!(bucket.try_acquire(10.0, later), Ok(()));
+        assert!(bucket.try_acquire(1.0, later).is_err());
+    }
+
+    #[test]
+    fn weight_above_capacity_takes_the_whole_bucket() {
+        let mut bucket = bucket(10.0, 1000.0);
+        let start = bucket.last_refill;
+        assert_eq!(bucket.try_acquire(25.0, start), Ok(()));
+        assert_eq!(bucket.try_acquire(25.0, start), Err(Duration::from_secs(1)));
+        assert_eq!(
+            bucket.try_acquire(25.0, start + Duration::from_secs(1)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        assert_eq!(configure("{"), RateLimitStatus::InvalidConfig);
+        assert_eq!(
+            configure(r#"{"key":"test.invalid","limit":0,"interval_ms":1000}"#),
+            RateLimitStatus::NonPositive
+        );
+        assert_eq!(
+            configure(r#"{"key":"test.invalid","limit":1,"interval_ms":-1}"#),
+            RateLimitStatus::NonPositive
+        );
+        assert_eq!(capacity("test.invalid"), None);
+    }
+
+    #[tokio::test]
+    async fn fail_fast_only_applies_to_fail_fast_buckets() {
+        let config = r#"{"key":"test.fail_fast","limit":1,"interval_ms":20,"fail_fast":true}"#;
+        assert_eq!(configure(config), RateLimitStatus::Ok);
+        assert_eq!(capacity("test.fail_fast"), Some(1.0));
+        acquire("test.fail_fast", 1.0, true).await.unwrap();
+        let err = acquire("test.fail_fast", 1.0, true).await.unwrap_err();
+        assert_eq!(err.key, "test.fail_fast");
+        assert!(err.wait <= Duration::from_millis(20));
+
+        // requests which do not fail fast wait for the refill instead
+        let started = Instant::now();
+        acquire("test.fail_fast", 1.0, false).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(10));
+
+        let config = r#"{"key":"test.waiting","limit":1,"interval_ms":20}"#;
+        assert_eq!(configure(config), RateLimitStatus::Ok);
+        acquire("test.waiting", 1.0, true).await.unwrap();
+        acquire("test.waiting", 1.0, true).await.unwrap();
+
+        assert!(acquire("test.unconfigured", 1e9, true).await.is_ok());
+    }
+}
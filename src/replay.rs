@@ -0,0 +1,245 @@
+//! Recording and replaying of the events taken by the strategies.
+//!
+//! If `GRASSHOPPER_RECORD` is set, every event returned by [`next_event`] is appended to the JSONL
+//! file at that path. If `GRASSHOPPER_REPLAY` is set, [`next_event`] reads the events of such a
+//! file instead of polling subscriptions, and sends are answered by the recorded responses.
+//!
+//! [`next_event`]: crate::event::next_event
+
+use std::{
+    collections::{HashMap, VecDeque},
+    env::var,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, LineWriter, Lines, Write},
+    num::NonZeroU64,
+    sync::Mutex,
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    clock,
+    event::{Event, ResponsePayload},
+    timer,
+};
+
+static RECORDER: Lazy<Option<Mutex<LineWriter<File>>>> = Lazy::new(|| {
+    let path = var("GRASSHOPPER_RECORD").ok()?;
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .expect("cannot open record file");
+    info!(path, "recording events");
+    Some(Mutex::new(LineWriter::new(file)))
+});
+
+static REPLAYER: Lazy<Option<Mutex<Replayer>>> = Lazy::new(|| {
+    let path = var("GRASSHOPPER_REPLAY").ok()?;
+    info!(path, "replaying events");
+    Some(Mutex::new(Replayer::open(&path)))
+});
+
+/// A line of a record file.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct EventRecord {
+    /// Milliseconds since the Unix epoch when the event was taken.
+    pub(crate) ts: u64,
+    pub(crate) kind: String,
+    pub(crate) token: Option<NonZeroU64>,
+    pub(crate) owners: Option<Vec<String>>,
+    pub(crate) url: Option<String>,
+    /// Response body, lossily converted to UTF-8.
+    pub(crate) content: Option<String>,
+    pub(crate) env_suffix: Option<String>,
+    pub(crate) headers: Option<HashMap<String, String>>,
+    pub(crate) status: u16,
+    pub(crate) error: bool,
+    pub(crate) restart: bool,
+    pub(crate) terminate: bool,
+}
+
+struct Replayer {
+    lines: Lines<BufReader<File>>,
+    line_number: usize,
+    /// Record read ahead while firing the timers due before it.
+    peeked: Option<EventRecord>,
+    /// Tokens of the sends waiting for a recorded response, oldest first.
+    pending_sends: VecDeque<NonZeroU64>,
+}
+
+impl Replayer {
+    fn open(path: &str) -> Self {
+        let file = File::open(path).expect("cannot open replay file");
+        Self {
+            lines: BufReader::new(file).lines(),
+            line_number: 0,
+            peeked: None,
+            pending_sends: VecDeque::new(),
+        }
+    }
+
+    fn next_event(&mut self) -> Event {
+        loop {
+            let Some(mut record) = self.peeked.take().or_else(|| self.read_record()) else {
+                info!("replay finished");
+                return Event::new("signal", ResponsePayload::new_terminator(), None);
+            };
+            let ts = Duration::from_millis(record.ts);
+            if let Some(ev) = timer::next_virtual_event(ts) {
+                self.peeked = Some(record);
+                return ev;
+            }
+            clock::advance_to(ts);
+            if matches!(
+                record.kind.as_str(),
+                "send_response" | "rate_limited" | "trading_halted"
+            ) {
+                // tokens of the recording cannot match the ones issued in this run, so responses
+                // are handed to the sends in order
+                match self.pending_sends.pop_front() {
+                    Some(token) => record.token = Some(token),
+                    None => {
+                        warn!(
+                            line = self.line_number,
+                            "no send waiting for recorded response"
+                        );
+                        continue;
+                    }
+                }
+            }
+            return Event::from_record(record);
+        }
+    }
+
+    fn read_record(&mut self) -> Option<EventRecord> {
+        loop {
+            let line = self.lines.next()?;
+            self.line_number += 1;
+            let line = line.expect("cannot read replay file");
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: EventRecord = match serde_json::from_str(&line) {
+                Ok(x) => x,
+                Err(err) => {
+                    error!(%err, line = self.line_number, "cannot parse recorded event");
+                    continue;
+                }
+            };
+            // timers are scheduled again by the strategies, and fire on the virtual clock
+            if record.kind != "timer" {
+                return Some(record);
+            }
+        }
+    }
+}
+
+/// Appends `ev` to the record file, if recording.
+pub(crate) fn record(ev: &Event) {
+    let Some(recorder) = RECORDER.as_ref() else {
+        return;
+    };
+    let ts = clock::epoch().as_millis() as u64;
+    let mut line = serde_json::to_string(&ev.to_record(ts)).unwrap();
+    line.push('\n');
+    if let Err(err) = recorder.lock().unwrap().write_all(line.as_bytes()) {
+        error!(%err, "cannot record event");
+    }
+}
+
+pub(crate) fn is_replaying() -> bool {
+    REPLAYER.is_some()
+}
+
+/// Returns the next recorded event, or [`None`] if not replaying.
+pub(crate) fn next_event() -> Option<Event> {
+    Some(REPLAYER.as_ref()?.lock().unwrap().next_event())
+}
+
+/// Registers a send which is answered by the next recorded response.
+pub(crate) fn expect_response(token: NonZeroU64) {
+    if let Some(replayer) = REPLAYER.as_ref() {
+        replayer.lock().unwrap().pending_sends.push_back(token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, num::NonZeroU64};
+
+    use super::{EventRecord, Replayer};
+    use crate::clock;
+
+    fn replayer(name: &str, kinds: &[&str]) -> Replayer {
+        let dir = std::env::temp_dir().join(format!("grasshopper-replay-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let lines = kinds
+            .iter()
+            .enumerate()
+            .map(|(i, kind)| {
+                format!(
+                    r#"{{"ts":{},"kind":"{kind}","token":1,"owners":null,"url":null,"content":"{i}","env_suffix":null,"headers":null,"status":200,"error":false,"restart":false,"terminate":false}}"#,
+                    1000 + i
+                )
+            })
+            .collect::<Vec<_>>();
+        fs::write(&path, lines.join("\n")).unwrap();
+        Replayer::open(path.to_str().unwrap())
+    }
+
+    fn next(replayer: &mut Replayer) -> EventRecord {
+        replayer.next_event().to_record(0)
+    }
+
+    fn token(x: u64) -> Option<NonZeroU64> {
+        NonZeroU64::new(x)
+    }
+
+    #[test]
+    fn fewer_sends_than_recorded() {
+        let _guard = clock::lock_virtual();
+        let mut replayer = replayer(
+            "fewer.jsonl",
+            &["fetcher", "send_response", "send_response", "fetcher"],
+        );
+        replayer.pending_sends.push_back(token(7).unwrap());
+
+        let ev = next(&mut replayer);
+        assert_eq!(
+            (ev.kind.as_str(), ev.content.as_deref()),
+            ("fetcher", Some("0"))
+        );
+        let ev = next(&mut replayer);
+        assert_eq!(ev.kind, "send_response");
+        assert_eq!((ev.token, ev.content.as_deref()), (token(7), Some("1")));
+        // the response without a waiting send is skipped
+        let ev = next(&mut replayer);
+        assert_eq!(
+            (ev.kind.as_str(), ev.content.as_deref()),
+            ("fetcher", Some("3"))
+        );
+        assert!(next(&mut replayer).terminate);
+    }
+
+    #[test]
+    fn more_sends_than_recorded() {
+        let _guard = clock::lock_virtual();
+        let mut replayer = replayer("more.jsonl", &["send_response", "fetcher"]);
+        replayer
+            .pending_sends
+            .extend([token(7).unwrap(), token(8).unwrap()]);
+
+        let ev = next(&mut replayer);
+        assert_eq!((ev.token, ev.content.as_deref()), (token(7), Some("0")));
+        let ev = next(&mut replayer);
+        assert_eq!(ev.kind, "fetcher");
+        // the replay ends with the later send still waiting
+        assert!(next(&mut replayer).terminate);
+        assert_eq!(Vec::from(replayer.pending_sends), [token(8).unwrap()]);
+    }
+}
@@ -35,5 +35,11 @@ pub(crate) fn sign(payload: RequestPayload) -> eyre::Result<RequestPayload> {
         sign: None,
         primary_only: payload.primary_only,
         env_suffix: payload.env_suffix,
+        timeout_ms: payload.timeout_ms,
+        retries: payload.retries,
+        retry_on_status: payload.retry_on_status,
+        http_version: payload.http_version,
+        rate_limit_key: payload.rate_limit_key,
+        weight: payload.weight,
     })
 }
@@ -0,0 +1,158 @@
+//! Log files per strategy, enabled by `GRASSHOPPER_LOG_PER_STRATEGY`.
+//!
+//! Events inside the `logging` span of [`crate::logging`] are written to `<script_name>.log` in
+//! the log directory instead of the main log file, with the same rotation, filter and format.
+//! The script name is the one set by [`crate::logging::set_script_name`] when the event was
+//! logged, and each file is opened on the first event of its strategy and closed by
+//! [`close_strategy_log`] when the strategy is unloaded.
+
+use std::{cell::RefCell, collections::BTreeMap, io, sync::Mutex};
+
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id},
+    Metadata, Subscriber,
+};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_subscriber::{
+    fmt::{writer::EitherWriter, MakeWriter},
+    layer::Context,
+    registry::LookupSpan,
+    Layer,
+};
+
+use crate::log_config::LogConfig;
+
+thread_local! {
+    /// Script names of the `logging` spans entered by this thread, innermost last.
+    static ENTERED_SCRIPTS: RefCell<Vec<Box<str>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Open log files by script name, with the guards flushing them when dropped.
+static WRITERS: Mutex<BTreeMap<Box<str>, (NonBlocking, WorkerGuard)>> = Mutex::new(BTreeMap::new());
+
+/// Returns the script name of the innermost `logging` span entered by this thread.
+fn current_script() -> Option<Box<str>> {
+    ENTERED_SCRIPTS.with(|x| x.borrow().last().cloned())
+}
+
+/// Returns whether this thread is inside the `logging` span of a strategy.
+pub(crate) fn in_strategy() -> bool {
+    ENTERED_SCRIPTS.with(|x| !x.borrow().is_empty())
+}
+
+/// Returns whether `metadata` is the `logging` span of [`crate::logging`].
+fn is_logging_span(metadata: &Metadata) -> bool {
+    metadata.name() == "logging" && metadata.target() == "grasshopper::logging"
+}
+
+/// Filters the strategy log files: `logging` spans are always enabled, so that they are entered
+/// and their fields formatted, and other spans and events only inside strategies.
+pub(crate) fn is_strategy_log(metadata: &Metadata) -> bool {
+    (metadata.is_span() && is_logging_span(metadata)) || in_strategy()
+}
+
+/// Filters the main log file: spans are always enabled, and events only outside strategies.
+pub(crate) fn is_main_log(metadata: &Metadata) -> bool {
+    metadata.is_span() || !in_strategy()
+}
+
+/// Flushes and closes the log file of a strategy. It is opened again on the next event of the
+/// strategy.
+pub(crate) fn close_strategy_log(script_name: &str) {
+    // dropping the guard waits for the flush, so not under the lock
+    let writer = WRITERS.lock().unwrap().remove(script_name);
+    drop(writer);
+}
+
+struct ScriptName(Box<str>);
+
+#[derive(Default)]
+struct ScriptNameVisitor(Option<Box<str>>);
+
+impl Visit for ScriptNameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "script_name" {
+            self.0 = Some(value.into());
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+/// Tracks the `logging` spans entered by each thread for [`in_strategy`]. It must not be
+/// filtered, so that spans disabled by log filters are tracked as well.
+pub(crate) struct ScriptSpanLayer;
+
+impl<S> Layer<S> for ScriptSpanLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let metadata = attrs.metadata();
+        if !is_logging_span(metadata) {
+            return;
+        }
+        let mut visitor = ScriptNameVisitor::default();
+        attrs.record(&mut visitor);
+        if let (Some(script_name), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().insert(ScriptName(script_name));
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        if let Some(ScriptName(script_name)) = extensions.get::<ScriptName>() {
+            ENTERED_SCRIPTS.with(|x| x.borrow_mut().push(script_name.clone()));
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if span.extensions().get::<ScriptName>().is_some() {
+            ENTERED_SCRIPTS.with(|x| x.borrow_mut().pop());
+        }
+    }
+}
+
+/// Writes to the log file of the current strategy, or nowhere outside of strategies.
+pub(crate) struct StrategyWriters {
+    config: LogConfig,
+}
+
+impl StrategyWriters {
+    pub(crate) fn new(config: LogConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl<'a> MakeWriter<'a> for StrategyWriters {
+    type Writer = EitherWriter<NonBlocking, io::Sink>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        let Some(script_name) = current_script() else {
+            return EitherWriter::B(io::sink());
+        };
+        let mut writers = WRITERS.lock().unwrap();
+        if let Some((writer, _)) = writers.get(&script_name) {
+            return EitherWriter::A(writer.clone());
+        }
+        match self.config.file_writer(&format!("{script_name}.log")) {
+            Ok(file) => {
+                let (writer, guard) = tracing_appender::non_blocking(file);
+                writers.insert(script_name, (writer.clone(), guard));
+                EitherWriter::A(writer)
+            }
+            Err(e) => {
+                // logging here would re-enter the subscriber
+                eprintln!("cannot open log file of {script_name}: {e}");
+                EitherWriter::B(io::sink())
+            }
+        }
+    }
+}
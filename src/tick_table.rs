@@ -0,0 +1,267 @@
+//! Price unit(tick size) schedules of exchanges whose units depend on the price.
+
+use std::{collections::HashMap, time::Duration};
+
+use grasshopper_macros::lua_export;
+use once_cell::sync::Lazy;
+use rust_decimal::{Decimal, RoundingStrategy};
+use tracing::error;
+
+use crate::{clock, lua_decimal::FfiDecimal, math_utils, LuaStr};
+
+/// Schedules of each exchange, oldest first. To change a schedule, append a version with the
+/// time it takes effect instead of editing the current one, so that replays of older records
+/// keep the units of their time.
+static SCHEDULES: Lazy<HashMap<&'static str, Vec<TickSchedule>>> = Lazy::new(|| {
+    HashMap::from([
+        (
+            "upbit",
+            vec![TickSchedule::new(
+                0,
+                &[
+                    (Some("0.1"), "0.0001"),
+                    (Some("1"), "0.001"),
+                    (Some("10"), "0.01"),
+                    (Some("100"), "1"),
+                    (Some("1000"), "1"),
+                    (Some("10000"), "5"),
+                    (Some("100000"), "10"),
+                    (Some("500000"), "50"),
+                    (Some("1000000"), "100"),
+                    (Some("2000000"), "500"),
+                    (None, "1000"),
+                ],
+            )],
+        ),
+        (
+            "bithumb",
+            vec![TickSchedule::new(
+                0,
+                &[
+                    (Some("1"), "0.001"),
+                    (Some("10"), "0.01"),
+                    (Some("100"), "1"),
+                    (Some("10000"), "5"),
+                    (Some("50000"), "10"),
+                    (Some("100000"), "50"),
+                    (Some("500000"), "100"),
+                    (Some("1000000"), "500"),
+                    (None, "1000"),
+                ],
+            )],
+        ),
+    ])
+});
+
+struct TickSchedule {
+    /// Milliseconds since the Unix epoch from which the schedule is in effect.
+    effective_from: u64,
+    tiers: Vec<Tier>,
+}
+
+/// Prices below `upper`(and not below the previous tier) are multiples of `unit`.
+struct Tier {
+    /// Exclusive upper bound of prices, or [`None`] for the last tier.
+    upper: Option<Decimal>,
+    unit: Decimal,
+}
+
+impl TickSchedule {
+    fn new(effective_from: u64, tiers: &[(Option<&str>, &str)]) -> Self {
+        Self {
+            effective_from,
+            tiers: tiers
+                .iter()
+                .map(|(upper, unit)| Tier {
+                    upper: upper.map(|x| x.parse().unwrap()),
+                    unit: unit.parse().unwrap(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the price unit at `price`, which is the one of the upper tier at tier boundaries.
+    fn unit(&self, price: Decimal) -> Decimal {
+        self.tiers
+            .iter()
+            .find(|x| x.upper.is_none_or(|upper| price < upper))
+            .unwrap()
+            .unit
+    }
+
+    /// Returns the price unit below `price`, which is the one of the lower tier at tier
+    /// boundaries.
+    fn unit_below(&self, price: Decimal) -> Decimal {
+        self.tiers
+            .iter()
+            .find(|x| x.upper.is_none_or(|upper| price <= upper))
+            .unwrap()
+            .unit
+    }
+
+    /// Returns the smallest valid price greater than `price`.
+    fn next_price_up(&self, price: Decimal) -> Decimal {
+        let unit = self.unit(price);
+        let ceiled = math_utils::round_to_step(price, unit, RoundingStrategy::ToPositiveInfinity)
+            .expect("price out of range");
+        if ceiled == price {
+            price + unit
+        } else {
+            ceiled
+        }
+    }
+
+    /// Returns the largest valid price less than `price`.
+    fn next_price_down(&self, price: Decimal) -> Decimal {
+        let unit = self.unit_below(price);
+        let floored = math_utils::round_to_step(price, unit, RoundingStrategy::ToNegativeInfinity)
+            .expect("price out of range");
+        if floored == price {
+            price - unit
+        } else {
+            floored
+        }
+    }
+
+    fn is_valid_price(&self, price: Decimal) -> bool {
+        price > Decimal::ZERO && (price % self.unit(price)).is_zero()
+    }
+}
+
+/// Returns the schedule of `exchange` in effect at `at` since the Unix epoch.
+fn schedule(exchange: &str, at: Duration) -> &'static TickSchedule {
+    let Some(schedules) = SCHEDULES.get(exchange) else {
+        error!(exchange, "unknown tick schedule");
+        panic!("no tick schedule for the exchange");
+    };
+    schedules
+        .iter()
+        .rev()
+        .find(|x| u128::from(x.effective_from) <= at.as_millis())
+        .unwrap_or(&schedules[0])
+}
+
+fn current_schedule(exchange: LuaStr) -> &'static TickSchedule {
+    schedule(unsafe { exchange.as_str() }, clock::epoch())
+}
+
+/// Returns the price unit at `price`. If `below` is set, returns the unit of the lower tier at
+/// tier boundaries instead, i.e. the unit to step down with.
+#[lua_export]
+pub extern "C-unwind" fn tick_price_unit(
+    exchange: LuaStr,
+    price: FfiDecimal,
+    below: bool,
+) -> FfiDecimal {
+    let schedule = current_schedule(exchange);
+    let price = Decimal::from(price);
+    if below {
+        schedule.unit_below(price).into()
+    } else {
+        schedule.unit(price).into()
+    }
+}
+
+#[lua_export]
+pub extern "C-unwind" fn tick_next_price_up(exchange: LuaStr, price: FfiDecimal) -> FfiDecimal {
+    current_schedule(exchange)
+        .next_price_up(Decimal::from(price))
+        .into()
+}
+
+#[lua_export]
+pub extern "C-unwind" fn tick_next_price_down(exchange: LuaStr, price: FfiDecimal) -> FfiDecimal {
+    current_schedule(exchange)
+        .next_price_down(Decimal::from(price))
+        .into()
+}
+
+#[lua_export]
+pub extern "C-unwind" fn tick_is_valid_price(exchange: LuaStr, price: FfiDecimal) -> bool {
+    current_schedule(exchange).is_valid_price(Decimal::from(price))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rust_decimal::Decimal;
+
+    use super::{schedule, SCHEDULES};
+
+    fn d(x: &str) -> Decimal {
+        x.parse().unwrap()
+    }
+
+    #[test]
+    fn every_boundary() {
+        for (exchange, schedules) in SCHEDULES.iter() {
+            for schedule in schedules {
+                for pair in schedule.tiers.windows(2) {
+                    let (lower, upper) = (&pair[0], &pair[1]);
+                    let boundary = lower.upper.unwrap();
+                    let context = format!("{exchange} at {boundary}");
+
+                    assert_eq!(schedule.unit(boundary), upper.unit, "{context}");
+                    assert_eq!(schedule.unit_below(boundary), lower.unit, "{context}");
+                    assert_eq!(
+                        schedule.unit(boundary - lower.unit),
+                        lower.unit,
+                        "{context}"
+                    );
+                    assert!(schedule.is_valid_price(boundary), "{context}");
+                    assert!(schedule.is_valid_price(boundary - lower.unit), "{context}");
+                    assert!(schedule.is_valid_price(boundary + upper.unit), "{context}");
+                    assert_eq!(
+                        schedule.next_price_up(boundary),
+                        boundary + upper.unit,
+                        "{context}"
+                    );
+                    assert_eq!(
+                        schedule.next_price_down(boundary),
+                        boundary - lower.unit,
+                        "{context}"
+                    );
+                    assert_eq!(
+                        schedule.next_price_up(boundary - lower.unit),
+                        boundary,
+                        "{context}"
+                    );
+                    assert_eq!(
+                        schedule.next_price_down(boundary + upper.unit),
+                        boundary,
+                        "{context}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn upbit() {
+        let upbit = schedule("upbit", Duration::ZERO);
+        assert_eq!(upbit.unit(d("0.05")), d("0.0001"));
+        assert_eq!(upbit.unit(d("1999999")), d("500"));
+        assert_eq!(upbit.unit(d("2000000")), d("1000"));
+        assert_eq!(upbit.unit(d("123456789")), d("1000"));
+        assert_eq!(upbit.next_price_up(d("12341")), d("12350"));
+        assert_eq!(upbit.next_price_down(d("12341")), d("12340"));
+        assert_eq!(upbit.next_price_down(d("1999999")), d("1999500"));
+        assert!(!upbit.is_valid_price(d("12341")));
+        assert!(!upbit.is_valid_price(d("500050")));
+        assert!(upbit.is_valid_price(d("500100")));
+        assert!(!upbit.is_valid_price(Decimal::ZERO));
+    }
+
+    #[test]
+    fn bithumb() {
+        let bithumb = schedule("bithumb", Duration::ZERO);
+        assert_eq!(bithumb.unit(d("99")), d("1"));
+        assert_eq!(bithumb.unit(d("5000")), d("5"));
+        assert_eq!(bithumb.next_price_up(d("49999")), d("50000"));
+        assert_eq!(bithumb.next_price_up(d("50000")), d("50050"));
+        assert_eq!(bithumb.next_price_down(d("100000")), d("99950"));
+        assert!(!bithumb.is_valid_price(d("1000500")));
+        assert!(bithumb.is_valid_price(d("1001000")));
+    }
+}
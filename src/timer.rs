@@ -0,0 +1,182 @@
+use std::{collections::HashMap, num::NonZeroU64, sync::Mutex, time::Duration};
+
+use grasshopper_macros::lua_export;
+use once_cell::sync::Lazy;
+use tokio::{
+    sync::oneshot,
+    time::{interval_at, Instant, MissedTickBehavior},
+};
+use tracing::debug;
+
+use crate::{
+    clock,
+    event::{next_token, push_event, Event, ResponsePayload},
+    logging::current_script_name,
+    RUNTIME_HANDLE,
+};
+
+static TIMERS: Lazy<Mutex<HashMap<NonZeroU64, Timer>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+struct Timer {
+    /// Name of the strategy which scheduled the timer.
+    owner: String,
+    kill: Option<oneshot::Sender<()>>,
+    /// Next deadline(since the Unix epoch) and period of timers on the virtual clock.
+    virtual_schedule: Option<(Duration, Option<Duration>)>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(kill) = self.kill.take() {
+            let _ = kill.send(());
+        }
+    }
+}
+
+async fn timer_task(
+    token: NonZeroU64,
+    delay: Duration,
+    repeat: bool,
+    mut krx: oneshot::Receiver<()>,
+) {
+    let mut interval = interval_at(Instant::now() + delay, delay);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => (),
+            _ = &mut krx => return,
+        }
+        push_event(Event::new(
+            "timer",
+            ResponsePayload::new_empty(),
+            Some(token),
+        ))
+        .await;
+        if !repeat {
+            break;
+        }
+    }
+    TIMERS.lock().unwrap().remove(&token);
+}
+
+/// Schedules a `"timer"` event with the returned token after `delay_ms` milliseconds, and every
+/// `delay_ms` milliseconds after that if `repeat` is set.
+#[lua_export]
+pub extern "C-unwind" fn schedule_timer(delay_ms: f64, repeat: bool) -> NonZeroU64 {
+    let token = next_token();
+    // zero-period intervals panic
+    let delay = Duration::from_secs_f64(delay_ms.max(1.0) / 1000.0);
+    let owner = current_script_name().unwrap_or_default();
+    if clock::is_virtual() {
+        TIMERS.lock().unwrap().insert(
+            token,
+            Timer {
+                owner,
+                kill: None,
+                virtual_schedule: Some((clock::epoch() + delay, repeat.then_some(delay))),
+            },
+        );
+        return token;
+    }
+    let (ktx, krx) = oneshot::channel();
+    TIMERS.lock().unwrap().insert(
+        token,
+        Timer {
+            owner,
+            kill: Some(ktx),
+            virtual_schedule: None,
+        },
+    );
+    RUNTIME_HANDLE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .unwrap()
+        .spawn(timer_task(token, delay, repeat, krx));
+    token
+}
+
+/// Cancels the timer. Events which are already queued are still delivered.
+#[lua_export]
+pub extern "C-unwind" fn cancel_timer(token: NonZeroU64) {
+    if TIMERS.lock().unwrap().remove(&token).is_none() {
+        debug!(token, "cancelling unknown or expired timer");
+    }
+}
+
+/// Cancels every timer scheduled by the strategy.
+pub(crate) fn cancel_all(owner: &str) {
+    TIMERS
+        .lock()
+        .unwrap()
+        .retain(|_, timer| timer.owner != owner);
+}
+
+/// Fires the earliest virtual timer due until `until`, advancing the virtual clock to its
+/// deadline. Repeating timers which are late fire once and are rescheduled from the current
+/// time, like the ones on the runtime.
+pub(crate) fn next_virtual_event(until: Duration) -> Option<Event> {
+    let mut timers = TIMERS.lock().unwrap();
+    let (token, deadline) = timers
+        .iter()
+        .filter_map(|(token, x)| Some((*token, x.virtual_schedule?.0)))
+        .filter(|(_, deadline)| *deadline <= until)
+        .min_by_key(|(token, deadline)| (*deadline, *token))?;
+    let fired_at = deadline.max(clock::epoch());
+    clock::advance_to(fired_at);
+    let timer = timers.get_mut(&token).unwrap();
+    match timer.virtual_schedule.unwrap().1 {
+        Some(period) => timer.virtual_schedule = Some((fired_at + period, Some(period))),
+        None => {
+            timers.remove(&token);
+        }
+    }
+    Some(Event::new(
+        "timer",
+        ResponsePayload::new_empty(),
+        Some(token),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{cancel_timer, next_virtual_event, schedule_timer};
+    use crate::clock;
+
+    #[test]
+    fn virtual_timers_fire_before_later_record() {
+        let _guard = clock::lock_virtual();
+        clock::advance_to(Duration::from_secs(1_000_000));
+        let start = clock::epoch();
+        let once = schedule_timer(100.0, false);
+        let repeating = schedule_timer(30.0, true);
+
+        // a record 100ms later is preceded by the timers due until then, in deadline order
+        let record = start + Duration::from_millis(100);
+        let mut fired = Vec::new();
+        while let Some(ev) = next_virtual_event(record) {
+            fired.push((ev.to_record(0).token.unwrap(), clock::epoch() - start));
+        }
+        let ms = Duration::from_millis;
+        assert_eq!(
+            fired,
+            [
+                (repeating, ms(30)),
+                (repeating, ms(60)),
+                (repeating, ms(90)),
+                (once, ms(100)),
+            ]
+        );
+        assert_eq!(clock::epoch(), record);
+
+        // the repeating timer is rescheduled after the record, and the one-shot one is gone
+        let later = start + Duration::from_millis(200);
+        let ev = next_virtual_event(later).unwrap();
+        assert_eq!(ev.to_record(0).token, Some(repeating));
+        assert_eq!(clock::epoch() - start, ms(120));
+        cancel_timer(repeating);
+        assert!(next_virtual_event(later).is_none());
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex as StdMutex, OnceLock},
+    time::Duration,
+};
+
+use eyre::eyre;
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use tokio::{
+    sync::{oneshot, Mutex},
+    time::sleep,
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{debug, error, info, instrument};
+
+use crate::event::{push_event, Event, ResponsePayload};
+
+pub(crate) static DEFAULT_WS_SUBSCRIPTIONS: OnceLock<WsSubscriptions> = OnceLock::new();
+
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A WebSocket stream subscription.
+///
+/// `url` identifies the stream on the Lua side(see `router.register`), so subscriptions which
+/// share a URL are delivered to the same route.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Hash)]
+pub struct WsPayload {
+    pub(crate) url: String,
+    /// Text frames sent right after each (re)connection.
+    #[serde(default)]
+    pub(crate) subscribe: Vec<String>,
+}
+
+pub struct WsSubscriber {
+    /// Number of live subscriptions per subscriber(strategy) name.
+    subscribers: Arc<StdMutex<HashMap<String, usize>>>,
+    kill: Option<oneshot::Sender<()>>,
+}
+
+impl WsSubscriber {
+    pub fn new(payload: WsPayload) -> Self {
+        let subscribers = Arc::new(StdMutex::new(HashMap::new()));
+        let (ktx, krx) = oneshot::channel();
+        tokio::spawn(Self::task(payload, Arc::clone(&subscribers), krx));
+        Self {
+            subscribers,
+            kill: Some(ktx),
+        }
+    }
+
+    async fn task(
+        payload: WsPayload,
+        subscribers: Arc<StdMutex<HashMap<String, usize>>>,
+        mut krx: oneshot::Receiver<()>,
+    ) {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let result = tokio::select! {
+                r = Self::stream(&payload, &subscribers, &mut backoff) => r,
+                _ = &mut krx => break,
+            };
+            if let Err(e) = result {
+                error!(
+                    %payload.url,
+                    error = Box::from(e) as Box<dyn std::error::Error>,
+                    ?backoff,
+                    "websocket disconnected, reconnecting"
+                );
+            }
+            tokio::select! {
+                _ = sleep(backoff) => (),
+                _ = &mut krx => break,
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Connects to the stream and pushes every text frame to the event queue until the connection
+    /// closes.
+    async fn stream(
+        payload: &WsPayload,
+        subscribers: &StdMutex<HashMap<String, usize>>,
+        backoff: &mut Duration,
+    ) -> eyre::Result<()> {
+        let (mut stream, _) = connect_async(payload.url.as_str()).await?;
+        for frame in &payload.subscribe {
+            stream.send(Message::Text(frame.clone())).await?;
+        }
+        info!(%payload.url, "websocket connected");
+        *backoff = MIN_BACKOFF;
+
+        while let Some(message) = stream.next().await {
+            match message? {
+                Message::Text(text) => {
+                    let owners = subscribers
+                        .lock()
+                        .unwrap()
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    push_event(
+                        Event::new("ws", ResponsePayload::from_string(&payload.url, text), None)
+                            .with_owners(&owners),
+                    )
+                    .await
+                }
+                Message::Close(frame) => return Err(eyre!("connection closed: {frame:?}")),
+                // pings are answered by tungstenite itself
+                x => debug!(?x, "ignoring non-text frame"),
+            }
+        }
+        Err(eyre!("stream ended"))
+    }
+}
+
+impl Drop for WsSubscriber {
+    fn drop(&mut self) {
+        let _ = self.kill.take().unwrap().send(());
+    }
+}
+
+#[derive(Clone)]
+pub struct WsSubscriptions {
+    subscribers: Arc<Mutex<HashMap<WsPayload, WsSubscriber>>>,
+}
+
+impl WsSubscriptions {
+    pub fn new() -> Self {
+        Self {
+            subscribers: Arc::new(Mutex::const_new(HashMap::new())),
+        }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn subscribe(&self, payload: WsPayload, subscriber: String) {
+        let mut subscribers = self.subscribers.lock().await;
+
+        let ws_subscriber = subscribers.entry(payload.clone()).or_insert_with(|| {
+            info!("new websocket subscription created");
+            WsSubscriber::new(payload)
+        });
+        *ws_subscriber
+            .subscribers
+            .lock()
+            .unwrap()
+            .entry(subscriber)
+            .or_default() += 1;
+    }
+
+    /// Releases every subscription of `subscriber`, e.g. when its strategy is reloaded.
+    #[instrument(skip(self))]
+    pub async fn unsubscribe_all(&self, subscriber: String) {
+        let mut subscribers = self.subscribers.lock().await;
+
+        subscribers.retain(|payload, ws_subscriber| {
+            let mut owners = ws_subscriber.subscribers.lock().unwrap();
+            owners.remove(&subscriber);
+            if owners.is_empty() {
+                info!(%payload.url, "websocket subscription removed");
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::{
+        net::TcpListener,
+        sync::{mpsc::Receiver, Notify},
+        time::timeout,
+    };
+    use tokio_tungstenite::{accept_async, tungstenite::Message};
+
+    use super::{WsPayload, WsSubscriptions};
+    use crate::{
+        event::{install_test_queue, Event},
+        replay::EventRecord,
+    };
+
+    /// Serves two connections, answering the first text frame of each with
+    /// `<connection>:<frame>`. The first connection is dropped once `drop_first` is notified.
+    async fn serve(listener: TcpListener, drop_first: Arc<Notify>) {
+        for n in 1..=2 {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = accept_async(tcp).await.unwrap();
+            let Some(Ok(Message::Text(frame))) = ws.next().await else {
+                panic!("no subscribe frame");
+            };
+            ws.send(Message::Text(format!("{n}:{frame}")))
+                .await
+                .unwrap();
+            if n == 1 {
+                drop_first.notified().await;
+            } else {
+                // the client hangs up when the subscription is removed
+                while let Some(Ok(_)) = ws.next().await {}
+            }
+        }
+    }
+
+    /// Returns the content and sorted owners of the next `ws` event from `url`.
+    async fn next_frame(queue: &mut Receiver<Event>, url: &str) -> (String, Vec<String>) {
+        let ev = timeout(Duration::from_secs(5), queue.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let EventRecord {
+            kind,
+            url: ev_url,
+            content,
+            owners,
+            ..
+        } = ev.to_record(0);
+        assert_eq!(kind, "ws");
+        assert_eq!(ev_url.as_deref(), Some(url));
+        let mut owners = owners.unwrap();
+        owners.sort();
+        (content.unwrap(), owners)
+    }
+
+    #[tokio::test]
+    async fn frames_are_routed_to_owners_across_reconnects() {
+        let mut queue = install_test_queue().await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let payload = WsPayload {
+            url: format!("ws://{}", listener.local_addr().unwrap()),
+            subscribe: vec!["sub".to_string()],
+        };
+        let drop_first = Arc::new(Notify::new());
+        let server = tokio::spawn(serve(listener, Arc::clone(&drop_first)));
+
+        let subscriptions = WsSubscriptions::new();
+        for subscriber in ["a", "b"] {
+            subscriptions
+                .subscribe(payload.clone(), subscriber.to_string())
+                .await;
+        }
+
+        assert_eq!(
+            next_frame(&mut queue, &payload.url).await,
+            ("1:sub".to_string(), vec!["a".to_string(), "b".to_string()])
+        );
+        subscriptions.unsubscribe_all("a".to_string()).await;
+        drop_first.notify_one();
+        // subscribe frames are sent again after reconnecting
+        assert_eq!(
+            next_frame(&mut queue, &payload.url).await,
+            ("2:sub".to_string(), vec!["b".to_string()])
+        );
+
+        subscriptions.unsubscribe_all("b".to_string()).await;
+        assert!(subscriptions.subscribers.lock().await.is_empty());
+        timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}